@@ -60,6 +60,7 @@ pub fn get_socket_params(prefix: &str, player: &str) -> MockSocketParams {
         latency_mean: ping * 0.5,
         latency_std_dev: std_dev * 0.5,
         loss,
+        loss_correlation: 0.0,
     };
 
     MockSocketParams {
@@ -0,0 +1,306 @@
+//! Runs a scenario file through the headless simulation harness and prints a
+//! summary of the recorded metrics.
+//!
+//! Usage: `untimely-sim <scenario.ron> [--csv <path>] [--json <path>] [--svg <path>]`
+
+use std::{borrow::Cow, collections::BTreeMap, env, error::Error, fmt::Write as _, fs, process};
+
+use untimely::{
+    metrics::Gauge,
+    sim::{run_harness, GaugeSummary, HarnessReport, MoveGame, MoveInput, Scenario},
+};
+
+const USAGE: &str =
+    "Usage: untimely-sim <scenario.ron> [--csv <path>] [--json <path>] [--svg <path>]";
+
+#[derive(Debug, Default)]
+struct Args {
+    scenario: String,
+    csv: Option<String>,
+    json: Option<String>,
+    svg: Option<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = env::args().skip(1);
+    let mut result = Args::default();
+    let mut scenario = None;
+
+    while let Some(arg) = args.next() {
+        let output = match arg.as_str() {
+            "--csv" => &mut result.csv,
+            "--json" => &mut result.json,
+            "--svg" => &mut result.svg,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ if scenario.is_none() => {
+                scenario = Some(arg);
+                continue;
+            }
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        };
+
+        *output = Some(
+            args.next()
+                .ok_or_else(|| format!("Missing path after {}", arg))?,
+        );
+    }
+
+    result.scenario = scenario.ok_or_else(|| USAGE.to_string())?;
+    Ok(result)
+}
+
+fn summary_table(report: &HarnessReport) -> String {
+    let mut table = format!(
+        "{:<32} {:>8} {:>10} {:>10} {:>10} {:>10}\n",
+        "gauge", "samples", "mean", "std", "min", "max"
+    );
+
+    for (name, summary) in report.gauge_summaries() {
+        let GaugeSummary {
+            len,
+            mean,
+            std,
+            min,
+            max,
+        } = summary;
+
+        writeln!(
+            table,
+            "{:<32} {:>8} {:>10.4} {:>10.4} {:>10.4} {:>10.4}",
+            name, len, mean, std, min, max
+        )
+        .unwrap();
+    }
+
+    table
+}
+
+fn to_csv(report: &HarnessReport) -> String {
+    let mut csv = "gauge,time,value\n".to_string();
+
+    for (name, gauge) in report.metrics.gauges() {
+        for (time, value) in gauge.plot_points() {
+            writeln!(csv, "{},{},{}", csv_field(name), time, value).unwrap();
+        }
+    }
+
+    csv
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break.
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+fn to_json(report: &HarnessReport) -> String {
+    // JSON has no representation for infinity or NaN, so they become null.
+    let gauges: BTreeMap<_, Vec<_>> = report
+        .metrics
+        .gauges()
+        .map(|(name, gauge)| {
+            let points = gauge
+                .plot_points()
+                .into_iter()
+                .map(|(time, value)| (time, Some(value).filter(|value| value.is_finite())))
+                .collect();
+
+            (name, points)
+        })
+        .collect();
+
+    serde_json::to_string(&gauges).unwrap() + "\n"
+}
+
+/// Escape text for use in SVG content and attribute values.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Plot every gauge in its own panel, stacked vertically.
+fn to_svg(report: &HarnessReport) -> String {
+    const WIDTH: f64 = 800.0;
+    const PANEL_HEIGHT: f64 = 120.0;
+    const MARGIN: f64 = 20.0;
+
+    let gauges: Vec<(&String, &Gauge)> = report.metrics.gauges().collect();
+    let height = gauges.len() as f64 * (PANEL_HEIGHT + MARGIN) + MARGIN;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         font-family=\"sans-serif\" font-size=\"12\">\n",
+        WIDTH, height
+    );
+
+    for (index, (name, gauge)) in gauges.iter().enumerate() {
+        let points: Vec<_> = gauge
+            .plot_points()
+            .into_iter()
+            .filter(|(_, value)| value.is_finite())
+            .collect();
+        let top = MARGIN + index as f64 * (PANEL_HEIGHT + MARGIN);
+
+        let (min_time, max_time) = bounds(points.iter().map(|(time, _)| *time));
+        let (min_value, max_value) = bounds(points.iter().map(|(_, value)| *value));
+
+        let polyline: Vec<_> = points
+            .iter()
+            .map(|(time, value)| {
+                let x = MARGIN + (time - min_time) / (max_time - min_time) * (WIDTH - 2.0 * MARGIN);
+                let y = top + PANEL_HEIGHT
+                    - (value - min_value) / (max_value - min_value) * PANEL_HEIGHT;
+                format!("{:.1},{:.1}", x, y)
+            })
+            .collect();
+
+        writeln!(
+            svg,
+            "  <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#ccc\"/>",
+            MARGIN,
+            top,
+            WIDTH - 2.0 * MARGIN,
+            PANEL_HEIGHT
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            "  <text x=\"{}\" y=\"{}\">{} [{:.4}, {:.4}]</text>",
+            MARGIN + 4.0,
+            top + 14.0,
+            xml_escape(name),
+            min_value,
+            max_value
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            "  <polyline fill=\"none\" stroke=\"steelblue\" points=\"{}\"/>",
+            polyline.join(" ")
+        )
+        .unwrap();
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Returns the range of the values, widened so that it is never empty.
+fn bounds(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+        (min.min(value), max.max(value))
+    });
+
+    if min > max {
+        (0.0, 1.0)
+    } else if min == max {
+        (min - 0.5, max + 0.5)
+    } else {
+        (min, max)
+    }
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
+    let scenario: Scenario<MoveInput> = Scenario::load(&args.scenario)?;
+    let mut params = scenario.to_harness_params()?;
+
+    // Always run with a seed, so that the run can be reproduced by adding the
+    // printed seed to the scenario.
+    let seed = *params.seed.get_or_insert_with(rand::random);
+    println!("seed: {}", seed);
+
+    let report = run_harness(&params, MoveGame::default());
+
+    println!("server ticks: {}", report.num_server_ticks);
+    for (name, num_ticks) in report.num_client_ticks.iter() {
+        println!("{} ticks: {}", name, num_ticks);
+    }
+    println!();
+    print!("{}", summary_table(&report));
+
+    if let Some(path) = &args.csv {
+        fs::write(path, to_csv(&report))?;
+    }
+    if let Some(path) = &args.json {
+        fs::write(path, to_json(&report))?;
+    }
+    if let Some(path) = &args.svg {
+        fs::write(path, to_svg(&report))?;
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        process::exit(2);
+    });
+
+    if let Err(error) = run(&args) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use untimely::{sim::HarnessReport, LocalClock, LocalDt, Metrics};
+
+    use super::{to_csv, to_json, to_svg};
+
+    fn report() -> HarnessReport {
+        let mut clock = LocalClock::new();
+        let mut metrics = Metrics::new(LocalDt::from_secs(10.0), clock.clone());
+
+        metrics.record_gauge("plain", 1.0);
+        metrics.record_gauge("a,\"b\"\n<c>&", 2.0);
+        clock.advance(LocalDt::from_secs(1.0));
+        metrics.record_gauge("plain", f64::NAN);
+
+        HarnessReport {
+            metrics,
+            num_server_ticks: 0,
+            num_client_ticks: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_csv() {
+        assert_eq!(
+            to_csv(&report()),
+            "gauge,time,value\n\"a,\"\"b\"\"\n<c>&\",0,2\nplain,0,1\nplain,1,NaN\n"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let json: serde_json::Value = serde_json::from_str(&to_json(&report())).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "a,\"b\"\n<c>&": [[0.0, 2.0]],
+                "plain": [[0.0, 1.0], [1.0, null]],
+            })
+        );
+    }
+
+    #[test]
+    fn test_svg() {
+        let svg = to_svg(&report());
+
+        assert!(svg.contains("a,&quot;b&quot;\n&lt;c&gt;&amp; ["));
+        assert!(!svg.contains("<c>"));
+        assert_eq!(svg.matches("<polyline").count(), 2);
+    }
+}
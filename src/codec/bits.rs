@@ -0,0 +1,204 @@
+use super::{CodecError, FloatQuantizer};
+
+/// Maximum number of 7-bit groups in a varint that encodes a `u64`.
+const MAX_VARINT_GROUPS: u32 = 10;
+
+/// Returns the number of bits that are needed to encode any value in
+/// `min..=max`.
+pub fn bits_for_range(min: u64, max: u64) -> u32 {
+    assert!(min <= max);
+
+    64 - (max - min).leading_zeros()
+}
+
+/// Writes values into a buffer bit by bit.
+///
+/// Bits are written starting at the least significant bit of each byte. The
+/// last byte is padded with zero bits.
+#[derive(Debug, Clone, Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    num_bits: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn num_bits(&self) -> usize {
+        self.num_bits
+    }
+
+    pub fn num_bytes(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    /// Write the lowest `num_bits` bits of `value`.
+    pub fn write_bits(&mut self, value: u64, num_bits: u32) {
+        assert!(num_bits <= 64);
+        debug_assert!(num_bits == 64 || value >> num_bits == 0);
+
+        let mut value = value;
+        let mut remaining = num_bits;
+
+        while remaining > 0 {
+            let bit_offset = (self.num_bits % 8) as u32;
+            if bit_offset == 0 {
+                self.bytes.push(0);
+            }
+
+            let n = remaining.min(8 - bit_offset);
+            let chunk = (value & ((1 << n) - 1)) as u8;
+            *self.bytes.last_mut().unwrap() |= chunk << bit_offset;
+
+            value >>= n;
+            remaining -= n;
+            self.num_bits += n as usize;
+        }
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_bits(value as u64, 1);
+    }
+
+    /// Write a value in `min..=max` using only as many bits as the range
+    /// needs.
+    pub fn write_bounded(&mut self, value: u64, min: u64, max: u64) {
+        assert!(
+            min <= value && value <= max,
+            "{} is not in {}..={}",
+            value,
+            min,
+            max
+        );
+
+        self.write_bits(value - min, bits_for_range(min, max));
+    }
+
+    /// Write an unsigned integer in groups of 7 bits, so that small values
+    /// take up less space.
+    pub fn write_varint(&mut self, value: u64) {
+        let mut value = value;
+
+        loop {
+            let group = value & 0x7f;
+            value >>= 7;
+
+            self.write_bits(group, 7);
+            self.write_bool(value != 0);
+
+            if value == 0 {
+                break;
+            }
+        }
+    }
+
+    /// Write a signed integer as a varint, so that values close to zero take
+    /// up less space.
+    pub fn write_signed_varint(&mut self, value: i64) {
+        // Zigzag encoding: 0, -1, 1, -2, 2, ... map to 0, 1, 2, 3, 4, ...
+        self.write_varint(((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    /// Write a float in `min..=max` with a precision of `num_bits` bits.
+    /// Values outside of the range are clamped.
+    pub fn write_quantized(&mut self, value: f64, min: f64, max: f64, num_bits: u32) {
+        FloatQuantizer::new(min, max, num_bits).write(self, value);
+    }
+}
+
+/// Reads values that have been written with a [`BitWriter`].
+#[derive(Debug, Clone)]
+pub struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub fn num_bits_read(&self) -> usize {
+        self.pos
+    }
+
+    pub fn num_bits_remaining(&self) -> usize {
+        self.bytes.len() * 8 - self.pos
+    }
+
+    pub fn read_bits(&mut self, num_bits: u32) -> Result<u64, CodecError> {
+        assert!(num_bits <= 64);
+
+        if self.num_bits_remaining() < num_bits as usize {
+            return Err(CodecError::UnexpectedEnd);
+        }
+
+        let mut value = 0;
+        let mut shift = 0;
+
+        while shift < num_bits {
+            let byte = self.bytes[self.pos / 8];
+            let bit_offset = (self.pos % 8) as u32;
+
+            let n = (num_bits - shift).min(8 - bit_offset);
+            let chunk = (byte >> bit_offset) as u64 & ((1 << n) - 1);
+            value |= chunk << shift;
+
+            shift += n;
+            self.pos += n as usize;
+        }
+
+        Ok(value)
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, CodecError> {
+        Ok(self.read_bits(1)? != 0)
+    }
+
+    pub fn read_bounded(&mut self, min: u64, max: u64) -> Result<u64, CodecError> {
+        let value = self.read_bits(bits_for_range(min, max))?;
+
+        if value <= max - min {
+            Ok(min + value)
+        } else {
+            Err(CodecError::OutOfRange)
+        }
+    }
+
+    pub fn read_varint(&mut self) -> Result<u64, CodecError> {
+        let mut value = 0;
+
+        for i in 0..MAX_VARINT_GROUPS {
+            let group = self.read_bits(7)?;
+            let shift = 7 * i;
+
+            // Only the lowest bit of the last group fits into a `u64`.
+            if shift == 63 && group > 1 {
+                return Err(CodecError::VarintOverflow);
+            }
+            value |= group << shift;
+
+            if !self.read_bool()? {
+                return Ok(value);
+            }
+        }
+
+        Err(CodecError::VarintOverflow)
+    }
+
+    pub fn read_signed_varint(&mut self) -> Result<i64, CodecError> {
+        let value = self.read_varint()?;
+
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    pub fn read_quantized(&mut self, min: f64, max: f64, num_bits: u32) -> Result<f64, CodecError> {
+        FloatQuantizer::new(min, max, num_bits).read(self)
+    }
+}
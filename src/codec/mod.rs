@@ -0,0 +1,398 @@
+//! A compact binary encoding for netcode messages.
+//!
+//! Values are packed bit by bit with a [`BitWriter`] and unpacked with a
+//! [`BitReader`]. Types implement [`NetSerialize`] to define their encoding.
+//! With the `derive` feature, `#[derive(NetSerialize)]` implements it for
+//! structs and enums by encoding their fields in declaration order.
+//!
+//! Continuous values can be encoded lossily with quantizers such as
+//! [`FloatQuantizer`] and [`QuaternionQuantizer`].
+
+mod bits;
+mod quantize;
+
+use std::fmt;
+
+use crate::{EntityId, GameDt, GameTime, LocalDt, PlayerId, TickNum};
+
+pub use bits::{bits_for_range, BitReader, BitWriter};
+pub use quantize::{
+    AngleQuantizer, FloatQuantizer, QuaternionQuantizer, Vector2Quantizer, Vector3Quantizer,
+    VectorQuantizer,
+};
+
+#[cfg(feature = "derive")]
+pub use untimely_derive::NetSerialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecError {
+    /// The buffer ended before the value was fully read.
+    UnexpectedEnd,
+
+    /// A bounded value or an enum variant index was out of range.
+    OutOfRange,
+
+    /// A varint did not fit into 64 bits.
+    VarintOverflow,
+
+    /// More bytes were left after decoding a value.
+    TrailingBytes,
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CodecError::UnexpectedEnd => write!(f, "Unexpected end of buffer"),
+            CodecError::OutOfRange => write!(f, "Value out of range"),
+            CodecError::VarintOverflow => write!(f, "Varint does not fit into 64 bits"),
+            CodecError::TrailingBytes => write!(f, "Trailing bytes after value"),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+/// Types with a stable binary encoding.
+pub trait NetSerialize: Sized {
+    fn serialize(&self, writer: &mut BitWriter);
+
+    fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError>;
+}
+
+/// Encode a single value into bytes.
+pub fn encode<T: NetSerialize>(value: &T) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    value.serialize(&mut writer);
+    writer.finish()
+}
+
+/// Decode a single value, requiring that it takes up all of the bytes.
+pub fn decode<T: NetSerialize>(bytes: &[u8]) -> Result<T, CodecError> {
+    let mut reader = BitReader::new(bytes);
+    let value = T::deserialize(&mut reader)?;
+
+    // At most the padding of the last byte may be left.
+    if reader.num_bits_remaining() < 8 {
+        Ok(value)
+    } else {
+        Err(CodecError::TrailingBytes)
+    }
+}
+
+impl NetSerialize for bool {
+    fn serialize(&self, writer: &mut BitWriter) {
+        writer.write_bool(*self);
+    }
+
+    fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+        reader.read_bool()
+    }
+}
+
+macro_rules! impl_fixed_width {
+    ($($ty:ty),*) => {
+        $(
+            impl NetSerialize for $ty {
+                fn serialize(&self, writer: &mut BitWriter) {
+                    writer.write_bits(*self as u64, <$ty>::BITS);
+                }
+
+                fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+                    Ok(reader.read_bits(<$ty>::BITS)? as $ty)
+                }
+            }
+        )*
+    };
+}
+
+// Larger integers are usually small in practice, so they are encoded as
+// varints.
+macro_rules! impl_varint {
+    ($($ty:ty),*) => {
+        $(
+            impl NetSerialize for $ty {
+                fn serialize(&self, writer: &mut BitWriter) {
+                    writer.write_varint(*self as u64);
+                }
+
+                fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+                    use std::convert::TryFrom;
+
+                    <$ty>::try_from(reader.read_varint()?).map_err(|_| CodecError::OutOfRange)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_signed_varint {
+    ($($ty:ty),*) => {
+        $(
+            impl NetSerialize for $ty {
+                fn serialize(&self, writer: &mut BitWriter) {
+                    writer.write_signed_varint(*self as i64);
+                }
+
+                fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+                    use std::convert::TryFrom;
+
+                    <$ty>::try_from(reader.read_signed_varint()?)
+                        .map_err(|_| CodecError::OutOfRange)
+                }
+            }
+        )*
+    };
+}
+
+impl_fixed_width!(u8, u16);
+impl_varint!(u32, u64, usize);
+impl_signed_varint!(i8, i16, i32, i64);
+
+impl NetSerialize for f32 {
+    fn serialize(&self, writer: &mut BitWriter) {
+        writer.write_bits(self.to_bits() as u64, 32);
+    }
+
+    fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+        Ok(f32::from_bits(reader.read_bits(32)? as u32))
+    }
+}
+
+impl NetSerialize for f64 {
+    fn serialize(&self, writer: &mut BitWriter) {
+        writer.write_bits(self.to_bits(), 64);
+    }
+
+    fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+        Ok(f64::from_bits(reader.read_bits(64)?))
+    }
+}
+
+impl<T: NetSerialize> NetSerialize for Option<T> {
+    fn serialize(&self, writer: &mut BitWriter) {
+        writer.write_bool(self.is_some());
+        if let Some(value) = self {
+            value.serialize(writer);
+        }
+    }
+
+    fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+        if reader.read_bool()? {
+            Ok(Some(T::deserialize(reader)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T: NetSerialize> NetSerialize for Vec<T> {
+    fn serialize(&self, writer: &mut BitWriter) {
+        writer.write_varint(self.len() as u64);
+        for value in self {
+            value.serialize(writer);
+        }
+    }
+
+    fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+        let len = reader.read_varint()?;
+
+        // The length may be corrupted, so check it against the remaining
+        // input before allocating. This assumes that every element takes up
+        // at least one bit.
+        if len > reader.num_bits_remaining() as u64 {
+            return Err(CodecError::UnexpectedEnd);
+        }
+
+        let mut values = Vec::with_capacity(len as usize);
+        for _ in 0..len {
+            values.push(T::deserialize(reader)?);
+        }
+
+        Ok(values)
+    }
+}
+
+impl<A: NetSerialize, B: NetSerialize> NetSerialize for (A, B) {
+    fn serialize(&self, writer: &mut BitWriter) {
+        self.0.serialize(writer);
+        self.1.serialize(writer);
+    }
+
+    fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+        Ok((A::deserialize(reader)?, B::deserialize(reader)?))
+    }
+}
+
+impl NetSerialize for TickNum {
+    fn serialize(&self, writer: &mut BitWriter) {
+        writer.write_varint(self.to_u64());
+    }
+
+    fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+        Ok(TickNum::from_u64(reader.read_varint()?))
+    }
+}
+
+impl NetSerialize for PlayerId {
+    fn serialize(&self, writer: &mut BitWriter) {
+        self.0.serialize(writer);
+    }
+
+    fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+        Ok(PlayerId(u32::deserialize(reader)?))
+    }
+}
+
+impl NetSerialize for EntityId {
+    fn serialize(&self, writer: &mut BitWriter) {
+        self.0.serialize(writer);
+    }
+
+    fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+        Ok(EntityId(u32::deserialize(reader)?))
+    }
+}
+
+// Times are encoded losslessly, so that both sides agree exactly on them.
+impl NetSerialize for GameTime {
+    fn serialize(&self, writer: &mut BitWriter) {
+        self.to_secs().serialize(writer);
+    }
+
+    fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+        Ok(GameTime::from_secs(f64::deserialize(reader)?))
+    }
+}
+
+impl NetSerialize for GameDt {
+    fn serialize(&self, writer: &mut BitWriter) {
+        self.to_secs().serialize(writer);
+    }
+
+    fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+        Ok(GameDt::from_secs(f64::deserialize(reader)?))
+    }
+}
+
+impl NetSerialize for LocalDt {
+    fn serialize(&self, writer: &mut BitWriter) {
+        self.to_secs().serialize(writer);
+    }
+
+    fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+        Ok(LocalDt::from_secs(f64::deserialize(reader)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, BitReader, BitWriter, CodecError, NetSerialize};
+    use crate::{EntityId, GameTime, PlayerId, TickNum};
+
+    #[derive(Debug, PartialEq)]
+    struct Empty;
+
+    impl NetSerialize for Empty {
+        fn serialize(&self, _: &mut BitWriter) {}
+
+        fn deserialize(_: &mut BitReader<'_>) -> Result<Self, CodecError> {
+            Ok(Empty)
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = BitWriter::new();
+        writer.write_bool(true);
+        writer.write_bounded(5, 3, 10);
+        writer.write_varint(0);
+        writer.write_varint(300);
+        writer.write_varint(u64::MAX);
+        writer.write_signed_varint(-1);
+        writer.write_signed_varint(i64::MIN);
+        writer.write_quantized(0.25, -1.0, 1.0, 10);
+        writer.write_bits(0xdead_beef, 32);
+
+        // 1 + 3 + 8 + 16 + 80 + 8 + 80 + 10 + 32 bits
+        assert_eq!(writer.num_bits(), 238);
+        assert_eq!(writer.num_bytes(), 30);
+
+        let bytes = writer.finish();
+        let mut reader = BitReader::new(&bytes);
+        assert!(reader.read_bool().unwrap());
+        assert_eq!(reader.read_bounded(3, 10).unwrap(), 5);
+        assert_eq!(reader.read_varint().unwrap(), 0);
+        assert_eq!(reader.read_varint().unwrap(), 300);
+        assert_eq!(reader.read_varint().unwrap(), u64::MAX);
+        assert_eq!(reader.read_signed_varint().unwrap(), -1);
+        assert_eq!(reader.read_signed_varint().unwrap(), i64::MIN);
+        assert!((reader.read_quantized(-1.0, 1.0, 10).unwrap() - 0.25).abs() <= 1.0 / 1023.0);
+        assert_eq!(reader.read_bits(32).unwrap(), 0xdead_beef);
+        assert_eq!(reader.read_bits(8), Err(CodecError::UnexpectedEnd));
+
+        let message = (
+            (TickNum::from_u64(1234), GameTime::from_secs(1.0 / 3.0)),
+            vec![
+                (PlayerId(0), Some(EntityId(7))),
+                (PlayerId(3), None),
+                (PlayerId(u32::MAX), Some(EntityId(0))),
+            ],
+        );
+        let bytes = encode(&message);
+        assert_eq!(decode(&bytes), Ok(message));
+
+        assert_eq!(decode::<PlayerId>(&[0x80]), Err(CodecError::UnexpectedEnd));
+        assert_eq!(decode::<u8>(&[1, 2]), Err(CodecError::TrailingBytes));
+        assert_eq!(
+            decode::<Vec<Empty>>(&encode(&u64::MAX)),
+            Err(CodecError::UnexpectedEnd)
+        );
+        assert_eq!(
+            u32::deserialize(&mut BitReader::new(&encode(&u64::MAX))),
+            Err(CodecError::OutOfRange)
+        );
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derive() {
+        #[derive(NetSerialize, Debug, Clone, PartialEq)]
+        struct Input {
+            left: bool,
+            right: bool,
+            jump: bool,
+        }
+
+        #[derive(NetSerialize, Debug, Clone, PartialEq)]
+        struct Wrapper<T>(T, u8);
+
+        #[derive(NetSerialize, Debug, Clone, PartialEq)]
+        enum Message {
+            Ping,
+            Input { tick: TickNum, input: Input },
+            Chat(PlayerId, Vec<u8>),
+        }
+
+        let messages = vec![
+            Message::Ping,
+            Message::Input {
+                tick: TickNum::from_u64(10),
+                input: Input {
+                    left: true,
+                    right: false,
+                    jump: true,
+                },
+            },
+            Message::Chat(PlayerId(2), b"gg".to_vec()),
+        ];
+
+        // The variant index takes 2 bits, so a ping fits into a single byte.
+        assert_eq!(encode(&messages[0]), vec![0]);
+        // 2 bits for the variant, 8 bits for the tick and 3 bits for the input.
+        assert_eq!(encode(&messages[1]).len(), 2);
+
+        let wrapped = Wrapper(messages, 42);
+        assert_eq!(decode(&encode(&wrapped)), Ok(wrapped));
+        assert_eq!(decode::<Message>(&[3]), Err(CodecError::OutOfRange));
+    }
+}
@@ -0,0 +1,396 @@
+//! Lossy encodings for continuous values.
+//!
+//! Every quantizer has a `snap` method that returns exactly the value that
+//! the receiver will decode. The sender should apply it to its own state
+//! after encoding, and client-side prediction should apply it to predicted
+//! state, so that both sides continue simulating from bit-identical values.
+
+use std::f64::consts::{PI, SQRT_2};
+
+use crate::time::{Dt, Time};
+
+use super::{BitReader, BitWriter, CodecError};
+
+/// Encodes floats in `min..=max` with `num_bits` bits. Values outside of the
+/// range are clamped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FloatQuantizer {
+    pub min: f64,
+    pub max: f64,
+    pub num_bits: u32,
+}
+
+impl FloatQuantizer {
+    pub fn new(min: f64, max: f64, num_bits: u32) -> Self {
+        assert!(min < max);
+        assert!((1..=32).contains(&num_bits));
+
+        Self { min, max, num_bits }
+    }
+
+    fn max_quantized(&self) -> u64 {
+        (1 << self.num_bits) - 1
+    }
+
+    /// Distance between two neighboring values that can be represented.
+    pub fn step(&self) -> f64 {
+        (self.max - self.min) / self.max_quantized() as f64
+    }
+
+    /// Maximum error of `snap` for values within the range.
+    pub fn max_error(&self) -> f64 {
+        self.step() / 2.0
+    }
+
+    pub fn quantize(&self, value: f64) -> u64 {
+        let t = ((value - self.min) / (self.max - self.min)).clamp(0.0, 1.0);
+
+        (t * self.max_quantized() as f64).round() as u64
+    }
+
+    pub fn dequantize(&self, quantized: u64) -> f64 {
+        assert!(quantized <= self.max_quantized());
+
+        self.min + quantized as f64 * self.step()
+    }
+
+    pub fn snap(&self, value: f64) -> f64 {
+        self.dequantize(self.quantize(value))
+    }
+
+    pub fn write(&self, writer: &mut BitWriter, value: f64) {
+        writer.write_bits(self.quantize(value), self.num_bits);
+    }
+
+    pub fn read(&self, reader: &mut BitReader<'_>) -> Result<f64, CodecError> {
+        Ok(self.dequantize(reader.read_bits(self.num_bits)?))
+    }
+
+    /// Quantize a duration in seconds.
+    pub fn snap_dt<Tag>(&self, dt: Dt<Tag>) -> Dt<Tag> {
+        Dt::from_secs(self.snap(dt.to_secs()))
+    }
+
+    pub fn write_dt<Tag>(&self, writer: &mut BitWriter, dt: Dt<Tag>) {
+        self.write(writer, dt.to_secs());
+    }
+
+    pub fn read_dt<Tag>(&self, reader: &mut BitReader<'_>) -> Result<Dt<Tag>, CodecError> {
+        Ok(Dt::from_secs(self.read(reader)?))
+    }
+
+    /// Quantize a time in seconds. Since the range is fixed, this is mostly
+    /// useful for times relative to a known base, such as the time within a
+    /// tick.
+    pub fn snap_time<Tag>(&self, time: Time<Tag>) -> Time<Tag> {
+        Time::from_secs(self.snap(time.to_secs()))
+    }
+
+    pub fn write_time<Tag>(&self, writer: &mut BitWriter, time: Time<Tag>) {
+        self.write(writer, time.to_secs());
+    }
+
+    pub fn read_time<Tag>(&self, reader: &mut BitReader<'_>) -> Result<Time<Tag>, CodecError> {
+        Ok(Time::from_secs(self.read(reader)?))
+    }
+}
+
+/// Encodes angles in radians with `num_bits` bits.
+///
+/// Angles wrap around, so every value can be encoded. Decoded angles are in
+/// `-PI..PI`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AngleQuantizer {
+    pub num_bits: u32,
+}
+
+impl AngleQuantizer {
+    pub fn new(num_bits: u32) -> Self {
+        assert!((1..=32).contains(&num_bits));
+
+        Self { num_bits }
+    }
+
+    fn num_steps(&self) -> u64 {
+        1 << self.num_bits
+    }
+
+    pub fn step(&self) -> f64 {
+        2.0 * PI / self.num_steps() as f64
+    }
+
+    pub fn max_error(&self) -> f64 {
+        self.step() / 2.0
+    }
+
+    pub fn quantize(&self, angle: f64) -> u64 {
+        let t = angle.rem_euclid(2.0 * PI) / (2.0 * PI);
+
+        // Angles just below 2 PI round to the same value as 0.
+        (t * self.num_steps() as f64).round() as u64 % self.num_steps()
+    }
+
+    pub fn dequantize(&self, quantized: u64) -> f64 {
+        assert!(quantized < self.num_steps());
+
+        let angle = quantized as f64 * self.step();
+
+        if angle >= PI {
+            angle - 2.0 * PI
+        } else {
+            angle
+        }
+    }
+
+    pub fn snap(&self, angle: f64) -> f64 {
+        self.dequantize(self.quantize(angle))
+    }
+
+    pub fn write(&self, writer: &mut BitWriter, angle: f64) {
+        writer.write_bits(self.quantize(angle), self.num_bits);
+    }
+
+    pub fn read(&self, reader: &mut BitReader<'_>) -> Result<f64, CodecError> {
+        Ok(self.dequantize(reader.read_bits(self.num_bits)?))
+    }
+}
+
+/// Encodes vectors with a separate [`FloatQuantizer`] for each axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VectorQuantizer<const N: usize> {
+    pub axes: [FloatQuantizer; N],
+}
+
+pub type Vector2Quantizer = VectorQuantizer<2>;
+pub type Vector3Quantizer = VectorQuantizer<3>;
+
+impl<const N: usize> VectorQuantizer<N> {
+    pub fn new(axes: [FloatQuantizer; N]) -> Self {
+        Self { axes }
+    }
+
+    /// Use the same range and precision for every axis.
+    pub fn uniform(min: f64, max: f64, num_bits: u32) -> Self {
+        Self::new([FloatQuantizer::new(min, max, num_bits); N])
+    }
+
+    pub fn num_bits(&self) -> u32 {
+        self.axes.iter().map(|axis| axis.num_bits).sum()
+    }
+
+    /// Maximum Euclidean distance between a vector within the bounds and its
+    /// snapped value.
+    pub fn max_error(&self) -> f64 {
+        self.axes
+            .iter()
+            .map(|axis| axis.max_error().powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    pub fn snap(&self, vector: [f64; N]) -> [f64; N] {
+        let mut snapped = vector;
+        for (value, axis) in snapped.iter_mut().zip(self.axes.iter()) {
+            *value = axis.snap(*value);
+        }
+
+        snapped
+    }
+
+    pub fn write(&self, writer: &mut BitWriter, vector: [f64; N]) {
+        for (value, axis) in vector.iter().zip(self.axes.iter()) {
+            axis.write(writer, *value);
+        }
+    }
+
+    pub fn read(&self, reader: &mut BitReader<'_>) -> Result<[f64; N], CodecError> {
+        let mut vector = [0.0; N];
+        for (value, axis) in vector.iter_mut().zip(self.axes.iter()) {
+            *value = axis.read(reader)?;
+        }
+
+        Ok(vector)
+    }
+}
+
+/// Encodes unit quaternions `[x, y, z, w]` with the smallest-three method.
+///
+/// The largest component is dropped and reconstructed from the others, which
+/// are at most `1 / sqrt(2)` in magnitude. Only its index is sent, using 2
+/// bits, so a quaternion takes up `2 + 3 * num_bits` bits. Since `q` and `-q`
+/// describe the same rotation, the largest component is made positive, and
+/// decoded quaternions may have the opposite sign of the original.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuaternionQuantizer {
+    component: FloatQuantizer,
+}
+
+impl QuaternionQuantizer {
+    pub fn new(num_bits: u32) -> Self {
+        Self {
+            component: FloatQuantizer::new(-1.0 / SQRT_2, 1.0 / SQRT_2, num_bits),
+        }
+    }
+
+    pub fn num_bits(&self) -> u32 {
+        2 + 3 * self.component.num_bits
+    }
+
+    /// Maximum error of the three smallest components. The error of the
+    /// reconstructed largest component can be up to about three times as
+    /// large.
+    pub fn max_component_error(&self) -> f64 {
+        self.component.max_error()
+    }
+
+    pub fn quantize(&self, quaternion: [f64; 4]) -> (usize, [u64; 3]) {
+        let norm = quaternion.iter().map(|c| c * c).sum::<f64>().sqrt();
+        assert!(norm > 0.0);
+
+        let largest = (0..4)
+            .max_by(|&i, &j| quaternion[i].abs().total_cmp(&quaternion[j].abs()))
+            .unwrap();
+        let sign = quaternion[largest].signum() / norm;
+
+        let mut quantized = [0; 3];
+        let others = (0..4).filter(|&i| i != largest);
+        for (value, i) in quantized.iter_mut().zip(others) {
+            *value = self.component.quantize(quaternion[i] * sign);
+        }
+
+        (largest, quantized)
+    }
+
+    pub fn dequantize(&self, largest: usize, quantized: [u64; 3]) -> [f64; 4] {
+        assert!(largest < 4);
+
+        let mut quaternion = [0.0; 4];
+        let others = (0..4).filter(|&i| i != largest);
+        for (value, i) in quantized.iter().zip(others) {
+            quaternion[i] = self.component.dequantize(*value);
+        }
+
+        let sum_squares: f64 = quaternion.iter().map(|c| c * c).sum();
+        quaternion[largest] = (1.0 - sum_squares).max(0.0).sqrt();
+
+        quaternion
+    }
+
+    pub fn snap(&self, quaternion: [f64; 4]) -> [f64; 4] {
+        let (largest, quantized) = self.quantize(quaternion);
+
+        self.dequantize(largest, quantized)
+    }
+
+    pub fn write(&self, writer: &mut BitWriter, quaternion: [f64; 4]) {
+        let (largest, quantized) = self.quantize(quaternion);
+
+        writer.write_bits(largest as u64, 2);
+        for value in quantized.iter() {
+            writer.write_bits(*value, self.component.num_bits);
+        }
+    }
+
+    pub fn read(&self, reader: &mut BitReader<'_>) -> Result<[f64; 4], CodecError> {
+        let largest = reader.read_bits(2)? as usize;
+
+        let mut quantized = [0; 3];
+        for value in quantized.iter_mut() {
+            *value = reader.read_bits(self.component.num_bits)?;
+        }
+
+        Ok(self.dequantize(largest, quantized))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use rand::Rng;
+
+    use super::{AngleQuantizer, FloatQuantizer, QuaternionQuantizer, Vector3Quantizer};
+    use crate::{
+        codec::{BitReader, BitWriter},
+        GameDt,
+    };
+
+    #[test]
+    fn test_error_bounds() {
+        let mut rng = rand::thread_rng();
+
+        let float = FloatQuantizer::new(-10.0, 10.0, 12);
+        let angle = AngleQuantizer::new(10);
+        let vector = Vector3Quantizer::uniform(-100.0, 100.0, 16);
+        let quaternion = QuaternionQuantizer::new(9);
+        let dt = FloatQuantizer::new(0.0, 0.1, 8);
+
+        for _ in 0..1000 {
+            let f = rng.gen_range(-10.0..=10.0);
+            let a = rng.gen_range(-4.0 * PI..4.0 * PI);
+            let v = [
+                rng.gen_range(-100.0..=100.0),
+                rng.gen_range(-100.0..=100.0),
+                rng.gen_range(-100.0..=100.0),
+            ];
+            let q = {
+                let q: [f64; 4] = [
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                ];
+                let norm = q.iter().map(|c| c * c).sum::<f64>().sqrt();
+                [q[0] / norm, q[1] / norm, q[2] / norm, q[3] / norm]
+            };
+            let d = GameDt::from_secs(rng.gen_range(0.0..=0.1));
+
+            let mut writer = BitWriter::new();
+            float.write(&mut writer, f);
+            angle.write(&mut writer, a);
+            vector.write(&mut writer, v);
+            quaternion.write(&mut writer, q);
+            dt.write_dt(&mut writer, d);
+
+            assert_eq!(
+                writer.num_bits() as u32,
+                12 + 10 + vector.num_bits() + quaternion.num_bits() + 8
+            );
+
+            let bytes = writer.finish();
+            let mut reader = BitReader::new(&bytes);
+
+            // What the receiver decodes is exactly what the sender snaps to.
+            let f2 = float.read(&mut reader).unwrap();
+            assert_eq!(f2, float.snap(f));
+            assert!((f2 - f).abs() <= float.max_error() + 1e-12);
+
+            let a2 = angle.read(&mut reader).unwrap();
+            assert_eq!(a2, angle.snap(a));
+            assert!((-PI..PI).contains(&a2));
+            let angle_error = (a2 - a).rem_euclid(2.0 * PI);
+            assert!(angle_error.min(2.0 * PI - angle_error) <= angle.max_error() + 1e-12);
+
+            let v2 = vector.read(&mut reader).unwrap();
+            assert_eq!(v2, vector.snap(v));
+            let vector_error = (0..3).map(|i| (v2[i] - v[i]).powi(2)).sum::<f64>().sqrt();
+            assert!(vector_error <= vector.max_error() + 1e-12);
+
+            let q2 = quaternion.read(&mut reader).unwrap();
+            assert_eq!(q2, quaternion.snap(q));
+            let dot: f64 = (0..4).map(|i| q[i] * q2[i]).sum();
+            let sign = dot.signum();
+            for i in 0..4 {
+                assert!((q2[i] * sign - q[i]).abs() <= 3.5 * quaternion.max_component_error());
+            }
+
+            let d2 = dt.read_dt(&mut reader).unwrap();
+            assert_eq!(d2, dt.snap_dt(d));
+            assert!((d2 - d).to_secs().abs() <= dt.max_error() + 1e-12);
+        }
+
+        // Out of range values are clamped.
+        assert_eq!(float.snap(20.0), 10.0);
+        assert_eq!(float.snap(-20.0), -10.0);
+    }
+}
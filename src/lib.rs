@@ -1,15 +1,22 @@
+// Lets `#[derive(NetSerialize)]` refer to `::untimely` inside of this crate too.
+extern crate self as untimely;
+
 mod tick;
 mod time;
 mod types;
 
+pub mod codec;
 pub mod join;
 pub mod metrics;
 pub mod mock;
+pub mod net;
+pub mod sim;
+pub mod transport;
 
 pub use metrics::Metrics;
 pub use tick::{DejitterBuffer, TickNum, TickPlayback, TickPlaybackParams};
 pub use time::{
-    GameDt, GameTime, LocalClock, LocalDt, LocalTime, PeriodicTimer, PlaybackClock,
-    PlaybackClockParams, Samples,
+    ClockSkew, GameDt, GameTime, LocalClock, LocalDt, LocalTime, PeriodicTimer, PlaybackClock,
+    PlaybackClockParams, Samples, SyncClock, TimeQueue, TimeWarp,
 };
 pub use types::{EntityId, PlayerId};
@@ -45,6 +45,34 @@ impl Gauge {
             .unwrap_or(f64::NAN)
     }
 
+    /// Returns the fraction of time in which the gauge's value satisfies
+    /// `predicate`.
+    ///
+    /// Each sample is taken to hold until the next sample. If the samples do
+    /// not span any time, the fraction of samples is returned instead.
+    pub fn time_fraction(&self, predicate: impl Fn(f64) -> bool) -> f64 {
+        let samples: Vec<_> = self.samples.iter().collect();
+        let total_time = match (samples.first(), samples.last()) {
+            (Some((first_time, _)), Some((last_time, _))) => (*last_time - *first_time).to_secs(),
+            _ => return f64::NAN,
+        };
+
+        if total_time > 0.0 {
+            samples
+                .windows(2)
+                .filter(|pair| predicate(*pair[0].1))
+                .map(|pair| (pair[1].0 - pair[0].0).to_secs())
+                .sum::<f64>()
+                / total_time
+        } else {
+            samples
+                .iter()
+                .filter(|(_, value)| predicate(**value))
+                .count() as f64
+                / samples.len() as f64
+        }
+    }
+
     pub fn plot_points(&self) -> Vec<(f64, f64)> {
         self.samples
             .iter()
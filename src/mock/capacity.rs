@@ -0,0 +1,45 @@
+use crate::{LocalDt, LocalTime};
+
+/// A size-aware model of a link.
+///
+/// Messages are put on the wire one after another at a limited rate, so large
+/// messages take longer and delay the messages behind them. Messages that
+/// exceed the MTU are dropped, like oversized datagrams on a real network.
+#[derive(Debug, Clone)]
+pub struct MockLinkCapacity {
+    pub bytes_per_sec: f64,
+    pub mtu: Option<usize>,
+
+    /// Messages that would wait longer than this for the link are dropped.
+    pub max_queue_delay: LocalDt,
+
+    busy_until: LocalTime,
+}
+
+impl MockLinkCapacity {
+    pub fn new(bytes_per_sec: f64, mtu: Option<usize>) -> Self {
+        Self {
+            bytes_per_sec,
+            mtu,
+            max_queue_delay: LocalDt::from_secs(1.0),
+            busy_until: LocalTime::zero(),
+        }
+    }
+
+    /// Put a message of the given size on the link at time `now`. Returns the
+    /// delay until the message has been transmitted completely, which is to
+    /// be added to the channel's latency, or `None` if it is dropped.
+    pub fn transmit(&mut self, now: LocalTime, size: usize) -> Option<LocalDt> {
+        if matches!(self.mtu, Some(mtu) if size > mtu) {
+            return None;
+        }
+
+        let start = self.busy_until.max(now);
+        if start - now > self.max_queue_delay {
+            return None;
+        }
+
+        self.busy_until = start + LocalDt::from_secs(size as f64 / self.bytes_per_sec);
+        Some(self.busy_until - now)
+    }
+}
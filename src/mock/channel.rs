@@ -1,15 +1,20 @@
-use std::{cmp::Ordering, collections::BinaryHeap};
-
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use rand_distr::{Distribution, Normal};
 
-use crate::{LocalClock, LocalDt, LocalTime};
+use crate::{LocalClock, LocalDt, LocalTime, TimeQueue};
 
 #[derive(Debug, Clone)]
 pub struct MockChannelParams {
     pub latency_mean: LocalDt,
     pub latency_std_dev: LocalDt,
     pub loss: f64,
+
+    /// Correlation between consecutive losses in `[0, 1]`.
+    ///
+    /// With a correlation of zero, losses are independent. Higher values
+    /// make losses come in bursts, while the overall loss rate stays at
+    /// `loss`.
+    pub loss_correlation: f64,
 }
 
 impl MockChannelParams {
@@ -18,11 +23,36 @@ impl MockChannelParams {
             latency_mean: LocalDt::zero(),
             latency_std_dev: LocalDt::zero(),
             loss: 0.0,
+            loss_correlation: 0.0,
+        }
+    }
+
+    pub fn interpolate(&self, other: &Self, alpha: f64) -> Self {
+        Self {
+            latency_mean: self.latency_mean + (other.latency_mean - self.latency_mean) * alpha,
+            latency_std_dev: self.latency_std_dev
+                + (other.latency_std_dev - self.latency_std_dev) * alpha,
+            loss: self.loss + (other.loss - self.loss) * alpha,
+            loss_correlation: self.loss_correlation
+                + (other.loss_correlation - self.loss_correlation) * alpha,
+        }
+    }
+
+    /// Returns the probability of losing a message, given whether the
+    /// previous message was lost.
+    ///
+    /// This is a two-state Markov model (Gilbert model) whose stationary loss
+    /// rate equals `loss`.
+    pub fn loss_probability(&self, last_lost: bool) -> f64 {
+        if last_lost {
+            self.loss + self.loss_correlation * (1.0 - self.loss)
+        } else {
+            self.loss * (1.0 - self.loss_correlation)
         }
     }
 
-    pub fn sample_residual<R: Rng>(&self, rng: &mut R) -> Option<LocalDt> {
-        if rng.gen::<f64>() < self.loss {
+    pub fn sample_residual<R: Rng>(&self, rng: &mut R, last_lost: bool) -> Option<LocalDt> {
+        if rng.gen::<f64>() < self.loss_probability(last_lost) {
             None
         } else {
             let distribution =
@@ -33,63 +63,68 @@ impl MockChannelParams {
     }
 }
 
-#[derive(Clone)]
-struct Message<T>(LocalTime, T);
-
 #[derive(Clone)]
 pub struct MockChannel<T> {
     clock: LocalClock,
-    messages_in_transit: BinaryHeap<Message<T>>,
+    messages_in_transit: TimeQueue<T>,
+    last_lost: bool,
+    rng: StdRng,
 }
 
 impl<T> MockChannel<T> {
     pub fn new(clock: LocalClock) -> Self {
         Self {
             clock,
-            messages_in_transit: BinaryHeap::new(),
+            messages_in_transit: TimeQueue::new(),
+            last_lost: false,
+            rng: StdRng::from_entropy(),
         }
     }
 
-    pub fn send(&mut self, params: &MockChannelParams, message: T) {
-        let rng = &mut rand::thread_rng();
+    /// Seed the random number generator, so that runs can be reproduced.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
 
-        if let Some(residual) = params.sample_residual(rng) {
-            let arrival_time = self.clock.local_time() + residual;
-            self.messages_in_transit
-                .push(Message(arrival_time, message));
-        }
+    /// Sample whether the next message is lost and, if not, its residual.
+    pub fn sample_residual(&mut self, params: &MockChannelParams) -> Option<LocalDt> {
+        let residual = params.sample_residual(&mut self.rng, self.last_lost);
+        self.last_lost = residual.is_none();
+
+        residual
     }
 
-    pub fn receive(&mut self) -> Option<(LocalTime, T)> {
-        if let Some(Message(oldest_arrival_time, _)) = self.messages_in_transit.peek() {
-            if self.clock.local_time() > *oldest_arrival_time {
-                let oldest_entry = self.messages_in_transit.pop().unwrap();
-                Some((oldest_entry.0, oldest_entry.1))
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+    pub fn send(&mut self, params: &MockChannelParams, message: T) -> Option<LocalTime> {
+        let residual = self.sample_residual(params);
+        self.send_with_residual(residual, message)
     }
-}
 
-impl<T> PartialOrd for Message<T> {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(&other))
+    /// Send a message with a given residual, dropping it if the residual is
+    /// `None`. Returns the arrival time of the message.
+    pub fn send_with_residual(
+        &mut self,
+        residual: Option<LocalDt>,
+        message: T,
+    ) -> Option<LocalTime> {
+        residual.map(|residual| {
+            let arrival_time = self.clock.local_time() + residual;
+            self.messages_in_transit.push(arrival_time, message);
+
+            arrival_time
+        })
     }
-}
 
-impl<T> Eq for Message<T> {}
+    /// Drop all messages that are currently in transit.
+    pub fn clear(&mut self) {
+        self.messages_in_transit.clear();
+    }
 
-impl<T> PartialEq for Message<T> {
-    fn eq(&self, other: &Self) -> bool {
-        self.cmp(&other) == Ordering::Equal
+    /// Returns the arrival time of the next message in transit.
+    pub fn next_arrival_time(&self) -> Option<LocalTime> {
+        self.messages_in_transit.peek_time()
     }
-}
 
-impl<T> Ord for Message<T> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        other.0.partial_cmp(&self.0).unwrap()
+    pub fn receive(&mut self) -> Option<(LocalTime, T)> {
+        self.messages_in_transit.pop_due(self.clock.local_time())
     }
 }
@@ -0,0 +1,192 @@
+use std::rc::Rc;
+
+use crate::{LocalDt, LocalTime};
+
+use super::{MockChannelParams, MockSocketParams};
+
+/// Time-varying [`MockChannelParams`], given as a pareen animation over the
+/// local time in seconds.
+///
+/// Conditions are built up by starting from a baseline and then layering
+/// events such as latency spikes or outages on top:
+///
+/// ```
+/// use untimely::{mock::{MockChannelParams, MockConditions}, LocalDt, LocalTime};
+///
+/// let conditions = MockConditions::constant(MockChannelParams::perfect())
+///     .latency_spike(LocalTime::from_secs(5.0), LocalDt::from_secs(1.0), LocalDt::from_millis(300.0))
+///     .outage(LocalTime::from_secs(10.0), LocalDt::from_secs(2.0));
+///
+/// assert_eq!(conditions.params_at(LocalTime::from_secs(11.0)).loss, 1.0);
+/// ```
+#[derive(Clone)]
+pub struct MockConditions(Rc<pareen::AnimBox<f64, MockChannelParams>>);
+
+impl MockConditions {
+    pub fn constant(params: MockChannelParams) -> Self {
+        Self::from_anim(pareen::constant(params))
+    }
+
+    pub fn from_anim<F>(anim: pareen::Anim<F>) -> Self
+    where
+        F: pareen::Fun<T = f64, V = MockChannelParams> + 'static,
+    {
+        MockConditions(Rc::new(anim.into_box()))
+    }
+
+    pub fn params_at(&self, time: LocalTime) -> MockChannelParams {
+        self.0.eval(time.to_secs())
+    }
+
+    /// Modify the params in the interval `[start, start + duration)`.
+    ///
+    /// The function `f` receives the progress through the interval in `[0,
+    /// 1)` as well as the params that would apply otherwise.
+    pub fn map_during<F>(self, start: LocalTime, duration: LocalDt, f: F) -> Self
+    where
+        F: Fn(f64, MockChannelParams) -> MockChannelParams + 'static,
+    {
+        let start = start.to_secs();
+        let end = start + duration.to_secs();
+
+        Self::from_anim(pareen::fun(move |t: f64| {
+            let params = self.0.eval(t);
+
+            if t >= start && t < end {
+                f((t - start) / (end - start), params)
+            } else {
+                params
+            }
+        }))
+    }
+
+    /// Temporarily increase the mean latency by `extra_latency`.
+    pub fn latency_spike(
+        self,
+        start: LocalTime,
+        duration: LocalDt,
+        extra_latency: LocalDt,
+    ) -> Self {
+        self.map_during(start, duration, move |_, params| MockChannelParams {
+            latency_mean: params.latency_mean + extra_latency,
+            ..params
+        })
+    }
+
+    /// Drop all messages for the given duration.
+    pub fn outage(self, start: LocalTime, duration: LocalDt) -> Self {
+        self.map_during(start, duration, |_, params| MockChannelParams {
+            loss: 1.0,
+            ..params
+        })
+    }
+
+    /// Gradually move towards the `target` params over the given duration,
+    /// and stay there afterwards.
+    pub fn degrade(self, start: LocalTime, duration: LocalDt, target: MockChannelParams) -> Self {
+        let end = (start + duration).to_secs();
+        let final_target = target.clone();
+
+        self.map_during(start, duration, move |alpha, params| {
+            params.interpolate(&target, alpha)
+        })
+        .map_during(
+            LocalTime::from_secs(end),
+            LocalDt::from_secs(f64::INFINITY),
+            move |_, _| final_target.clone(),
+        )
+    }
+
+    /// Permanently shift the latency by `shift`, starting at `time`. This
+    /// models a route change that changes the baseline RTT.
+    ///
+    /// Note that the shift applies to one direction only, so to change the
+    /// RTT by `x`, each of the two directions should be shifted by `x / 2`.
+    pub fn route_change(self, time: LocalTime, shift: LocalDt) -> Self {
+        self.map_during(time, LocalDt::from_secs(f64::INFINITY), move |_, params| {
+            MockChannelParams {
+                latency_mean: (params.latency_mean + shift).max(LocalDt::zero()),
+                ..params
+            }
+        })
+    }
+}
+
+/// Time-varying params of the two directions of a [`MockSocket`](super::MockSocket).
+#[derive(Clone)]
+pub struct MockSocketConditions {
+    pub server_out: MockConditions,
+    pub client_out: MockConditions,
+}
+
+impl MockSocketConditions {
+    pub fn constant(params: MockSocketParams) -> Self {
+        Self {
+            server_out: MockConditions::constant(params.server_out),
+            client_out: MockConditions::constant(params.client_out),
+        }
+    }
+
+    /// Use the same conditions in both directions.
+    pub fn symmetric(conditions: MockConditions) -> Self {
+        Self {
+            server_out: conditions.clone(),
+            client_out: conditions,
+        }
+    }
+
+    pub fn params_at(&self, time: LocalTime) -> MockSocketParams {
+        MockSocketParams {
+            server_out: self.server_out.params_at(time),
+            client_out: self.client_out.params_at(time),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MockConditions;
+    use crate::{mock::MockChannelParams, LocalDt, LocalTime};
+
+    #[test]
+    fn test_events() {
+        let conditions = MockConditions::constant(MockChannelParams::perfect())
+            .latency_spike(
+                LocalTime::from_secs(1.0),
+                LocalDt::from_secs(1.0),
+                LocalDt::from_secs(0.5),
+            )
+            .outage(LocalTime::from_secs(3.0), LocalDt::from_secs(1.0))
+            .route_change(LocalTime::from_secs(5.0), LocalDt::from_secs(0.1));
+
+        let at = |secs| conditions.params_at(LocalTime::from_secs(secs));
+
+        assert_eq!(at(0.5).latency_mean, LocalDt::zero());
+        assert_eq!(at(1.5).latency_mean, LocalDt::from_secs(0.5));
+        assert_eq!(at(2.5).latency_mean, LocalDt::zero());
+        assert_eq!(at(3.5).loss, 1.0);
+        assert_eq!(at(4.5).loss, 0.0);
+        assert_eq!(at(6.0).latency_mean, LocalDt::from_secs(0.1));
+    }
+
+    #[test]
+    fn test_degrade() {
+        let target = MockChannelParams {
+            latency_mean: LocalDt::from_secs(0.2),
+            latency_std_dev: LocalDt::zero(),
+            loss: 0.5,
+            loss_correlation: 0.0,
+        };
+        let conditions = MockConditions::constant(MockChannelParams::perfect()).degrade(
+            LocalTime::from_secs(1.0),
+            LocalDt::from_secs(2.0),
+            target,
+        );
+
+        let at = |secs| conditions.params_at(LocalTime::from_secs(secs));
+
+        assert_eq!(at(0.0).loss, 0.0);
+        assert!((at(2.0).loss - 0.25).abs() < 1e-9);
+        assert_eq!(at(10.0).loss, 0.5);
+    }
+}
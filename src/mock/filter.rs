@@ -0,0 +1,97 @@
+use std::rc::Rc;
+
+use crate::{LocalDt, LocalTime, PlayerId};
+
+use super::{MockDirection, MockLane};
+
+/// What should happen to a message that has been intercepted by a filter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockAction<T> {
+    /// Send the message, which may have been rewritten, as usual.
+    Pass(T),
+
+    /// Drop the message before it reaches the channel.
+    Drop,
+
+    /// Send the message with an additional delay on top of the channel's
+    /// latency.
+    Delay(T, LocalDt),
+
+    /// Send each of the messages. Every copy is subject to the channel's
+    /// conditions independently.
+    Duplicate(Vec<T>),
+}
+
+/// Information about a message that is being sent, given to filters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MockFilterContext {
+    pub player: PlayerId,
+    pub direction: MockDirection,
+    pub lane: MockLane,
+    pub send_time: LocalTime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MockFilterId(pub(crate) u64);
+
+type Filter<T> = Rc<dyn Fn(&MockFilterContext, T) -> MockAction<T>>;
+
+/// A chain of filters for messages of type `T`.
+pub(crate) struct MockFilters<T> {
+    filters: Vec<(MockFilterId, Filter<T>)>,
+}
+
+impl<T> Clone for MockFilters<T> {
+    fn clone(&self) -> Self {
+        Self {
+            filters: self.filters.clone(),
+        }
+    }
+}
+
+impl<T> Default for MockFilters<T> {
+    fn default() -> Self {
+        Self {
+            filters: Vec::new(),
+        }
+    }
+}
+
+impl<T> MockFilters<T> {
+    pub fn add(&mut self, id: MockFilterId, filter: Filter<T>) {
+        self.filters.push((id, filter));
+    }
+
+    pub fn remove(&mut self, id: MockFilterId) -> bool {
+        let len = self.filters.len();
+        self.filters.retain(|(filter_id, _)| *filter_id != id);
+        self.filters.len() < len
+    }
+
+    /// Pass a message through all filters in the order in which they were
+    /// added. Returns the messages to send, each with its additional delay.
+    pub fn apply(&self, context: &MockFilterContext, message: T) -> Vec<(T, LocalDt)> {
+        let mut messages = vec![(message, LocalDt::zero())];
+
+        for (_, filter) in self.filters.iter() {
+            let mut filtered = Vec::new();
+
+            for (message, delay) in messages {
+                match filter(context, message) {
+                    MockAction::Pass(message) => filtered.push((message, delay)),
+                    MockAction::Drop => (),
+                    MockAction::Delay(message, extra_delay) => {
+                        filtered.push((message, delay + extra_delay))
+                    }
+                    MockAction::Duplicate(copies) => {
+                        filtered.extend(copies.into_iter().map(|message| (message, delay)))
+                    }
+                }
+            }
+
+            messages = filtered;
+        }
+
+        messages
+    }
+}
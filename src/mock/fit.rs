@@ -0,0 +1,119 @@
+use crate::{LocalDt, LocalTime};
+
+use super::{MockChannelParams, MockSocketParams};
+
+/// One packet in a log of recorded packet timestamps.
+///
+/// If the send and receive times come from different machines, the estimated
+/// mean latency also contains the offset between the two clocks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArrivalLogEntry {
+    pub send_time: LocalTime,
+
+    /// The time at which the packet was received, or `None` if it was lost.
+    pub receive_time: Option<LocalTime>,
+}
+
+/// Estimate [`MockChannelParams`] that reproduce the given arrival log.
+///
+/// Returns `None` if the log is empty.
+pub fn fit_channel_params(log: &[ArrivalLogEntry]) -> Option<MockChannelParams> {
+    if log.is_empty() {
+        return None;
+    }
+
+    let mut log = log.to_vec();
+    log.sort_by(|entry1, entry2| entry1.send_time.partial_cmp(&entry2.send_time).unwrap());
+
+    let latencies: Vec<f64> = log
+        .iter()
+        .filter_map(|entry| {
+            entry
+                .receive_time
+                .map(|receive_time| (receive_time - entry.send_time).to_secs())
+        })
+        .collect();
+
+    let (latency_mean, latency_std_dev) = if latencies.is_empty() {
+        (0.0, 0.0)
+    } else {
+        let mean = latencies.iter().sum::<f64>() / latencies.len() as f64;
+        let var =
+            latencies.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / latencies.len() as f64;
+
+        (mean, var.sqrt())
+    };
+
+    let num_lost = log.len() - latencies.len();
+    let loss = num_lost as f64 / log.len() as f64;
+
+    // Estimate burstiness from how likely a loss is to follow another loss.
+    let (num_after_loss, num_lost_after_loss) = log
+        .windows(2)
+        .filter(|pair| pair[0].receive_time.is_none())
+        .fold((0, 0), |(num, num_lost), pair| {
+            (num + 1, num_lost + pair[1].receive_time.is_none() as usize)
+        });
+    let loss_correlation = if num_after_loss > 0 && loss < 1.0 {
+        let loss_after_loss = num_lost_after_loss as f64 / num_after_loss as f64;
+        ((loss_after_loss - loss) / (1.0 - loss)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    Some(MockChannelParams {
+        latency_mean: LocalDt::from_secs(latency_mean),
+        latency_std_dev: LocalDt::from_secs(latency_std_dev),
+        loss,
+        loss_correlation,
+    })
+}
+
+/// Estimate [`MockSocketParams`] from the arrival logs of both directions.
+pub fn fit_socket_params(
+    server_out_log: &[ArrivalLogEntry],
+    client_out_log: &[ArrivalLogEntry],
+) -> Option<MockSocketParams> {
+    Some(MockSocketParams {
+        server_out: fit_channel_params(server_out_log)?,
+        client_out: fit_channel_params(client_out_log)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fit_channel_params, ArrivalLogEntry};
+    use crate::{mock::MockChannelParams, LocalDt, LocalTime};
+
+    #[test]
+    fn test_fit_sampled_params() {
+        let params = MockChannelParams {
+            latency_mean: LocalDt::from_millis(80.0),
+            latency_std_dev: LocalDt::from_millis(10.0),
+            loss: 0.1,
+            loss_correlation: 0.5,
+        };
+
+        let rng = &mut rand::thread_rng();
+        let mut last_lost = false;
+        let log: Vec<_> = (0..100000)
+            .map(|i| {
+                let send_time = LocalTime::from_secs(i as f64 / 60.0);
+                let residual = params.sample_residual(rng, last_lost);
+                last_lost = residual.is_none();
+
+                ArrivalLogEntry {
+                    send_time,
+                    receive_time: residual.map(|residual| send_time + residual),
+                }
+            })
+            .collect();
+
+        let fit = fit_channel_params(&log).unwrap();
+
+        assert!((fit.latency_mean.to_secs() - 0.08).abs() < 0.001);
+        assert!((fit.latency_std_dev.to_secs() - 0.01).abs() < 0.001);
+        assert!((fit.loss - 0.1).abs() < 0.01);
+        assert!((fit.loss_correlation - 0.5).abs() < 0.05);
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+
+use crate::{LocalClock, LocalTime};
+
+use super::{MockChannel, MockChannelParams, MockConditions};
+
+#[derive(Clone)]
+struct MockLink<T> {
+    conditions: MockConditions,
+    channel: MockChannel<T>,
+}
+
+/// A simulated network of nodes with addresses of type `A`, connected by
+/// directed links that each have their own conditions.
+///
+/// In contrast to [`MockNet`](super::MockNet), this does not assume a
+/// client/server topology, so it can be used to simulate peer-to-peer meshes,
+/// relay servers or multiple servers. All messages are of type `T`.
+#[derive(Clone)]
+pub struct MockGraph<A, T> {
+    clock: LocalClock,
+    node_clocks: BTreeMap<A, LocalClock>,
+
+    // Links are indexed by the receiving node first, so that receiving only
+    // needs to look at incoming links.
+    links: BTreeMap<A, BTreeMap<A, MockLink<T>>>,
+}
+
+impl<A, T> MockGraph<A, T>
+where
+    A: Ord + Copy,
+{
+    /// Create an empty graph. The `clock` must be a root clock.
+    pub fn new(clock: LocalClock) -> Self {
+        assert!(!clock.is_derived(), "MockGraph needs a root LocalClock");
+
+        Self {
+            clock,
+            node_clocks: BTreeMap::new(),
+            links: BTreeMap::new(),
+        }
+    }
+
+    /// Create a graph in which every pair of nodes is connected in both
+    /// directions.
+    pub fn full_mesh(nodes: &[A], params: MockChannelParams, clock: LocalClock) -> Self {
+        let mut graph = Self::new(clock);
+        let conditions = MockConditions::constant(params);
+
+        for from in nodes {
+            for to in nodes {
+                if from != to {
+                    graph.connect(*from, *to, conditions.clone());
+                }
+            }
+        }
+
+        graph
+    }
+
+    pub fn links(&self) -> impl Iterator<Item = (A, A)> + '_ {
+        self.links
+            .iter()
+            .flat_map(|(to, incoming)| incoming.keys().map(move |from| (*from, *to)))
+    }
+
+    pub fn has_link(&self, from: A, to: A) -> bool {
+        self.links
+            .get(&to)
+            .is_some_and(|incoming| incoming.contains_key(&from))
+    }
+
+    /// Add a directed link from `from` to `to`, replacing any existing link.
+    /// Messages that are in transit on an existing link are lost.
+    pub fn connect(&mut self, from: A, to: A, conditions: MockConditions) {
+        let link = MockLink {
+            conditions,
+            channel: MockChannel::new(self.clock.clone()),
+        };
+
+        self.links.entry(to).or_default().insert(from, link);
+    }
+
+    /// Add links in both directions between `a` and `b`.
+    pub fn connect_both(&mut self, a: A, b: A, conditions: MockConditions) {
+        self.connect(a, b, conditions.clone());
+        self.connect(b, a, conditions);
+    }
+
+    /// Remove the directed link from `from` to `to`, losing all messages that
+    /// are in transit on it.
+    pub fn disconnect(&mut self, from: A, to: A) {
+        if let Some(incoming) = self.links.get_mut(&to) {
+            incoming.remove(&from);
+        }
+    }
+
+    /// Set the clock in which messages received by `node` are timestamped.
+    /// By default, the graph's root clock is used.
+    pub fn set_node_clock(&mut self, node: A, clock: LocalClock) {
+        self.node_clocks.insert(node, clock);
+    }
+
+    /// Remove all links from and to a node.
+    pub fn remove_node(&mut self, node: A) {
+        self.links.remove(&node);
+        self.node_clocks.remove(&node);
+
+        for incoming in self.links.values_mut() {
+            incoming.remove(&node);
+        }
+    }
+
+    pub fn set_link_conditions(&mut self, from: A, to: A, conditions: MockConditions) {
+        self.link_mut(from, to).conditions = conditions;
+    }
+
+    pub fn set_link_params(&mut self, from: A, to: A, params: MockChannelParams) {
+        self.set_link_conditions(from, to, MockConditions::constant(params));
+    }
+
+    pub fn send(&mut self, from: A, to: A, message: T) -> Option<LocalTime> {
+        let time = self.clock.local_time();
+        let link = self.link_mut(from, to);
+        let params = link.conditions.params_at(time);
+
+        link.channel.send(&params, message)
+    }
+
+    /// Returns the time at which the next message in transit arrives.
+    pub fn next_arrival_time(&self) -> Option<LocalTime> {
+        self.links
+            .values()
+            .flat_map(|incoming| incoming.values())
+            .filter_map(|link| link.channel.next_arrival_time())
+            .min_by(|time1, time2| time1.partial_cmp(time2).unwrap())
+    }
+
+    /// Receive all messages that have arrived at `node`, sorted by their
+    /// arrival time.
+    pub fn receive(&mut self, node: A) -> Vec<(LocalTime, A, T)> {
+        let mut messages = Vec::new();
+
+        if let Some(incoming) = self.links.get_mut(&node) {
+            for (sender, link) in incoming.iter_mut() {
+                while let Some((receive_time, message)) = link.channel.receive() {
+                    messages.push((receive_time, *sender, message));
+                }
+            }
+        }
+
+        messages.sort_by(|(time1, _, _), (time2, _, _)| time1.partial_cmp(time2).unwrap());
+
+        if let Some(node_clock) = self.node_clocks.get(&node) {
+            for (receive_time, _, _) in messages.iter_mut() {
+                *receive_time = node_clock.local_time_at(*receive_time);
+            }
+        }
+
+        messages
+    }
+
+    fn link_mut(&mut self, from: A, to: A) -> &mut MockLink<T> {
+        self.links
+            .get_mut(&to)
+            .and_then(|incoming| incoming.get_mut(&from))
+            .expect("Unknown link")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MockGraph;
+    use crate::{
+        mock::{MockChannelParams, MockConditions},
+        LocalClock, LocalDt, LocalTime,
+    };
+
+    fn latency(secs: f64) -> MockChannelParams {
+        MockChannelParams {
+            latency_mean: LocalDt::from_secs(secs),
+            ..MockChannelParams::perfect()
+        }
+    }
+
+    #[test]
+    fn test_full_mesh() {
+        let mut clock = LocalClock::new();
+        let mut graph = MockGraph::full_mesh(&[0, 1, 2], latency(0.25), clock.clone());
+
+        assert_eq!(graph.links().count(), 6);
+        assert!(graph.has_link(2, 0));
+        assert!(!graph.has_link(0, 0));
+
+        for from in 0..3 {
+            for to in 0..3 {
+                if from != to {
+                    graph.send(from, to, from * 10 + to);
+                }
+            }
+        }
+
+        clock.advance(LocalDt::from_secs(0.5));
+
+        for node in 0..3 {
+            let received: Vec<_> = graph
+                .receive(node)
+                .into_iter()
+                .map(|(time, sender, message)| {
+                    assert_eq!(time, LocalTime::from_secs(0.25));
+                    assert_eq!(message, sender * 10 + node);
+                    sender
+                })
+                .collect();
+            assert_eq!(received.len(), 2);
+            assert!(!received.contains(&node));
+        }
+
+        graph.remove_node(1);
+        assert_eq!(graph.links().collect::<Vec<_>>(), vec![(2, 0), (0, 2)]);
+    }
+
+    #[test]
+    fn test_relay() {
+        let mut clock = LocalClock::new();
+        let mut graph = MockGraph::new(clock.clone());
+        graph.connect_both(0, 1, MockConditions::constant(latency(0.25)));
+        graph.connect_both(1, 2, MockConditions::constant(latency(0.125)));
+
+        // Node 1 relays messages from node 0 to node 2, so the delays add up.
+        graph.send(0, 1, "hello");
+        let mut received = Vec::new();
+
+        while let Some(time) = graph.next_arrival_time() {
+            clock.advance(time - clock.local_time());
+
+            for (_, _, message) in graph.receive(1) {
+                graph.send(1, 2, message);
+            }
+            received.extend(graph.receive(2));
+        }
+
+        assert_eq!(received, vec![(LocalTime::from_secs(0.375), 1, "hello")]);
+    }
+
+    #[test]
+    fn test_link_params() {
+        let clock = LocalClock::new();
+        let mut graph = MockGraph::full_mesh(&[0, 1], latency(0.25), clock);
+
+        // Params are per directed link.
+        graph.set_link_params(
+            0,
+            1,
+            MockChannelParams {
+                loss: 1.0,
+                ..latency(0.25)
+            },
+        );
+        assert_eq!(graph.send(0, 1, ()), None);
+        assert_eq!(graph.send(1, 0, ()), Some(LocalTime::from_secs(0.25)));
+
+        graph.set_link_params(1, 0, latency(0.5));
+        assert_eq!(graph.send(1, 0, ()), Some(LocalTime::from_secs(0.5)));
+
+        graph.disconnect(1, 0);
+        assert!(!graph.has_link(1, 0));
+        assert!(graph.has_link(0, 1));
+    }
+}
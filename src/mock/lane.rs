@@ -0,0 +1,29 @@
+use crate::LocalDt;
+
+/// Delivery guarantees of a lane of a [`MockNet`](super::MockNet).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MockLaneKind {
+    /// Messages can be lost and can arrive out of order.
+    Unreliable,
+
+    /// Messages can be lost, and messages that arrive after a newer message
+    /// of the same lane are discarded.
+    UnreliableSequenced,
+
+    /// Messages are not lost while the socket stays connected, and they are
+    /// received in the order in which they were sent.
+    ///
+    /// A lost message is retransmitted after `retransmit_delay`. Messages that
+    /// arrive while an earlier message is still missing are held back until
+    /// it arrives, which leads to head-of-line blocking.
+    ReliableOrdered { retransmit_delay: LocalDt },
+}
+
+/// Identifies a lane of a [`MockNet`](super::MockNet).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MockLane(pub(crate) usize);
+
+impl MockLane {
+    /// The unreliable lane that every `MockNet` starts with.
+    pub const DEFAULT: MockLane = MockLane(0);
+}
@@ -1,5 +1,21 @@
+mod capacity;
 mod channel;
+mod conditions;
+mod filter;
+mod fit;
+mod graph;
+mod lane;
 mod net;
+mod stats;
+mod trace;
 
+pub use capacity::MockLinkCapacity;
 pub use channel::{MockChannel, MockChannelParams};
-pub use net::{MockNet, MockSocket, MockSocketParams};
+pub use conditions::{MockConditions, MockSocketConditions};
+pub use filter::{MockAction, MockFilterContext, MockFilterId};
+pub use fit::{fit_channel_params, fit_socket_params, ArrivalLogEntry};
+pub use graph::MockGraph;
+pub use lane::{MockLane, MockLaneKind};
+pub use net::{MockClientTransport, MockNet, MockSocket, MockSocketParams};
+pub use stats::{MockDirectionStats, MockSocketStats};
+pub use trace::{MockDirection, MockReplay, MockTrace, MockTraceEntry};
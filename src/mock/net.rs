@@ -1,8 +1,25 @@
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    rc::Rc,
+};
 
-use crate::{LocalClock, LocalTime, PlayerId};
+use rand::{rngs::StdRng, SeedableRng};
 
-use super::{MockChannel, MockChannelParams};
+use crate::{
+    transport::{ClientTransport, ServerTransport},
+    LocalClock, LocalDt, LocalTime, Metrics, PlayerId, TimeQueue,
+};
+
+use super::{
+    filter::MockFilters, MockAction, MockChannelParams, MockDirection, MockDirectionStats,
+    MockFilterContext, MockFilterId, MockLane, MockLaneKind, MockReplay, MockSocketConditions,
+    MockSocketStats, MockTrace, MockTraceEntry,
+};
+
+/// Reliable messages are given up on after this many failed transmissions,
+/// so that a permanently broken channel can not stall the simulation. The
+/// receiver then skips the abandoned message.
+const MAX_RELIABLE_ATTEMPTS: usize = 4096;
 
 #[derive(Clone, Debug)]
 pub struct MockSocketParams {
@@ -21,73 +38,1211 @@ impl MockSocketParams {
 
 #[derive(Clone)]
 pub struct MockSocket<S, C> {
-    params: MockSocketParams,
-    server_out: MockChannel<S>,
-    client_out: MockChannel<C>,
+    conditions: MockSocketConditions,
+    connected: bool,
+    client_clock: LocalClock,
+
+    // Messages in transit carry the epoch of the socket at the time of
+    // sending. Whenever a socket is disconnected, its epoch changes, so that
+    // messages in transit can be discarded on arrival.
+    epoch: u64,
+
+    server_out_lost: bool,
+    client_out_lost: bool,
+    stats: MockSocketStats,
+    lanes: BTreeMap<(MockDirection, MockLane), LaneState<S, C>>,
+
+    // Messages that have arrived, but have not been received yet. They are
+    // counted as delivered once they are received.
+    server_out_arrived: VecDeque<Arrived<S>>,
+    client_out_arrived: VecDeque<Arrived<C>>,
+}
+
+#[derive(Clone)]
+struct Arrived<T> {
+    arrival_time: LocalTime,
+    send_time: LocalTime,
+    size: usize,
+    message: T,
+}
+
+impl<T> Arrived<T> {
+    fn record_delivered(&self, stats: &mut MockDirectionStats) {
+        stats.record_delivered(self.size, self.arrival_time - self.send_time);
+    }
+}
+
+#[derive(Clone)]
+enum Payload<S, C> {
+    ToClient(S),
+    ToServer(C),
+}
+
+impl<S, C> Payload<S, C> {
+    fn direction(&self) -> MockDirection {
+        match self {
+            Payload::ToClient(_) => MockDirection::ServerToClient,
+            Payload::ToServer(_) => MockDirection::ClientToServer,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct InTransit<S, C> {
+    player: PlayerId,
+    epoch: u64,
+    lane: MockLane,
+    seq: u64,
+    send_time: LocalTime,
+    size: usize,
+    payload: Payload<S, C>,
+}
+
+/// Sequencing state of one direction of a lane of a socket.
+#[derive(Clone)]
+struct LaneState<S, C> {
+    next_send_seq: u64,
+    next_receive_seq: u64,
+
+    // Reliable messages that have arrived before an earlier message.
+    pending: BTreeMap<u64, InTransit<S, C>>,
+
+    // Reliable messages that were given up on, and that the receiver skips.
+    abandoned: BTreeSet<u64>,
+}
+
+impl<S, C> Default for LaneState<S, C> {
+    fn default() -> Self {
+        Self {
+            next_send_seq: 0,
+            next_receive_seq: 0,
+            pending: BTreeMap::new(),
+            abandoned: BTreeSet::new(),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct MockNet<S, C> {
     clock: LocalClock,
+    server_clock: LocalClock,
     sockets: BTreeMap<PlayerId, MockSocket<S, C>>,
+    next_epoch: u64,
+    lanes: Vec<(String, MockLaneKind)>,
+
+    // All messages in transit are kept in one global queue, so that finding
+    // the next arrival does not depend on the number of sockets.
+    in_transit: TimeQueue<InTransit<S, C>>,
+    rng: StdRng,
+
+    server_msg_size: fn(&S) -> usize,
+    client_msg_size: fn(&C) -> usize,
+    trace: Option<MockTrace>,
+    replay: Option<MockReplay>,
+
+    server_filters: MockFilters<S>,
+    client_filters: MockFilters<C>,
+    next_filter_id: u64,
+}
+
+fn size_of_msg<T>(_: &T) -> usize {
+    std::mem::size_of::<T>()
+}
+
+impl<S, C> MockSocket<S, C> {
+    fn new(clock: LocalClock, epoch: u64) -> Self {
+        Self {
+            conditions: MockSocketConditions::constant(MockSocketParams::perfect()),
+            connected: true,
+            client_clock: clock,
+            epoch,
+            server_out_lost: false,
+            client_out_lost: false,
+            stats: MockSocketStats::default(),
+            lanes: BTreeMap::new(),
+            server_out_arrived: VecDeque::new(),
+            client_out_arrived: VecDeque::new(),
+        }
+    }
+
+    fn reset(&mut self, connected: bool, epoch: u64) {
+        self.connected = connected;
+        self.epoch = epoch;
+
+        // Messages that have not been received yet are lost, including
+        // reliable messages that are held back for ordering.
+        for ((direction, _), state) in std::mem::take(&mut self.lanes) {
+            for _ in state.pending {
+                self.stats_mut(direction).record_dropped();
+            }
+        }
+        for _ in self.server_out_arrived.drain(..) {
+            self.stats.server_out.record_dropped();
+        }
+        for _ in self.client_out_arrived.drain(..) {
+            self.stats.client_out.record_dropped();
+        }
+    }
+
+    fn stats_mut(&mut self, direction: MockDirection) -> &mut MockDirectionStats {
+        match direction {
+            MockDirection::ServerToClient => &mut self.stats.server_out,
+            MockDirection::ClientToServer => &mut self.stats.client_out,
+        }
+    }
+
+    /// Sample the time until a message sent at `time` arrives, taking
+    /// retransmissions into account for reliable lanes.
+    fn sample_residual(
+        &mut self,
+        rng: &mut StdRng,
+        direction: MockDirection,
+        kind: MockLaneKind,
+        time: LocalTime,
+    ) -> Option<LocalDt> {
+        let (conditions, last_lost) = match direction {
+            MockDirection::ServerToClient => {
+                (&self.conditions.server_out, &mut self.server_out_lost)
+            }
+            MockDirection::ClientToServer => {
+                (&self.conditions.client_out, &mut self.client_out_lost)
+            }
+        };
+        let (max_attempts, retransmit_delay) = match kind {
+            MockLaneKind::ReliableOrdered { retransmit_delay } => {
+                (MAX_RELIABLE_ATTEMPTS, retransmit_delay)
+            }
+            _ => (1, LocalDt::zero()),
+        };
+
+        for attempt in 0..max_attempts {
+            let attempt_dt = retransmit_delay * attempt as f64;
+            let params = conditions.params_at(time + attempt_dt);
+            let residual = params.sample_residual(rng, *last_lost);
+            *last_lost = residual.is_none();
+
+            if let Some(residual) = residual {
+                return Some(attempt_dt + residual);
+            }
+        }
+
+        None
+    }
+
+    fn next_send_seq(&mut self, direction: MockDirection, lane: MockLane) -> u64 {
+        let state = self.lanes.entry((direction, lane)).or_default();
+        let seq = state.next_send_seq;
+        state.next_send_seq += 1;
+        seq
+    }
+
+    /// Give up on a reliable message, so that later messages of its lane are
+    /// not held back forever.
+    fn abandon(&mut self, direction: MockDirection, lane: MockLane, seq: u64) {
+        let state = self.lanes.entry((direction, lane)).or_default();
+        state.abandoned.insert(seq);
+        self.stats_mut(direction).record_abandoned();
+    }
+
+    /// Handle a message that has arrived at the socket, applying the delivery
+    /// guarantees of its lane.
+    fn arrive(&mut self, arrival_time: LocalTime, in_transit: InTransit<S, C>, kind: MockLaneKind) {
+        let direction = in_transit.payload.direction();
+        let state = self.lanes.entry((direction, in_transit.lane)).or_default();
+
+        let ready = match kind {
+            MockLaneKind::Unreliable => vec![in_transit],
+            MockLaneKind::UnreliableSequenced => {
+                if in_transit.seq >= state.next_receive_seq {
+                    state.next_receive_seq = in_transit.seq + 1;
+                    vec![in_transit]
+                } else {
+                    self.stats_mut(direction).record_dropped();
+                    return;
+                }
+            }
+            MockLaneKind::ReliableOrdered { .. } => {
+                state.pending.insert(in_transit.seq, in_transit);
+
+                // Messages that were held back are received together with the
+                // message that fills the gap.
+                let mut ready = Vec::new();
+                loop {
+                    if let Some(in_transit) = state.pending.remove(&state.next_receive_seq) {
+                        ready.push(in_transit);
+                    } else if !state.abandoned.remove(&state.next_receive_seq) {
+                        break;
+                    }
+                    state.next_receive_seq += 1;
+                }
+                ready
+            }
+        };
+
+        for in_transit in ready {
+            let InTransit {
+                send_time,
+                size,
+                payload,
+                ..
+            } = in_transit;
+
+            match payload {
+                Payload::ToClient(message) => {
+                    self.server_out_arrived.push_back(Arrived {
+                        arrival_time,
+                        send_time,
+                        size,
+                        message,
+                    });
+                }
+                Payload::ToServer(message) => {
+                    self.client_out_arrived.push_back(Arrived {
+                        arrival_time,
+                        send_time,
+                        size,
+                        message,
+                    });
+                }
+            }
+        }
+    }
 }
 
 impl<S, C> MockNet<S, C> {
+    /// Create a new `MockNet` with the given players.
+    ///
+    /// The `clock` must be a root clock. By default, it is also used to
+    /// timestamp received messages for all nodes, but each node can be given
+    /// its own derived clock.
     pub fn new(players: &[PlayerId], clock: LocalClock) -> Self {
+        assert!(!clock.is_derived(), "MockNet needs a root LocalClock");
+
         let sockets = players
             .iter()
-            .map(|player| {
-                (
-                    *player,
-                    MockSocket {
-                        params: MockSocketParams::perfect(),
-                        server_out: MockChannel::new(clock.clone()),
-                        client_out: MockChannel::new(clock.clone()),
-                    },
-                )
-            })
+            .enumerate()
+            .map(|(epoch, player)| (*player, MockSocket::new(clock.clone(), epoch as u64)))
             .collect();
 
-        MockNet { clock, sockets }
+        MockNet {
+            clock: clock.clone(),
+            server_clock: clock,
+            sockets,
+            next_epoch: players.len() as u64,
+            lanes: vec![("default".to_string(), MockLaneKind::Unreliable)],
+            in_transit: TimeQueue::new(),
+            rng: StdRng::from_entropy(),
+            server_msg_size: size_of_msg::<S>,
+            client_msg_size: size_of_msg::<C>,
+            trace: None,
+            replay: None,
+            server_filters: MockFilters::default(),
+            client_filters: MockFilters::default(),
+            next_filter_id: 0,
+        }
+    }
+
+    /// Seed the random number generator that decides the fate of messages,
+    /// so that runs can be reproduced.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Set the clock in which messages received by the server are
+    /// timestamped.
+    pub fn set_server_clock(&mut self, clock: LocalClock) {
+        self.server_clock = clock;
+    }
+
+    /// Set the clock in which messages received by `player` are timestamped.
+    pub fn set_client_clock(&mut self, player: PlayerId, clock: LocalClock) {
+        self.socket_mut(player).client_clock = clock;
+    }
+
+    /// Set the functions that determine the size of messages in bytes.
+    ///
+    /// By default, the in-memory size of the message types is used.
+    pub fn set_msg_size_fns(
+        &mut self,
+        server_msg_size: fn(&S) -> usize,
+        client_msg_size: fn(&C) -> usize,
+    ) {
+        self.server_msg_size = server_msg_size;
+        self.client_msg_size = client_msg_size;
+    }
+
+    /// Start recording the fate of every message that is sent from now on.
+    pub fn start_recording(&mut self) {
+        self.trace = Some(MockTrace::new());
+    }
+
+    /// Stop recording and return the recorded trace.
+    pub fn take_trace(&mut self) -> Option<MockTrace> {
+        self.trace.take()
+    }
+
+    /// Replay the delivery schedule of a previously recorded trace.
+    ///
+    /// Messages that are sent after the trace has been exhausted for their
+    /// lane and direction, as well as messages on reliable lanes, fall back
+    /// to the socket's current params.
+    pub fn replay(&mut self, trace: &MockTrace) {
+        self.replay = Some(MockReplay::new(trace));
+    }
+
+    pub fn stop_replay(&mut self) {
+        self.replay = None;
+    }
+
+    /// Add a lane with the given delivery guarantees to all sockets.
+    ///
+    /// Messages of all lanes share the socket's conditions and are received
+    /// together.
+    pub fn add_lane(&mut self, name: &str, kind: MockLaneKind) -> MockLane {
+        assert!(self.lane(name).is_none(), "Lane already exists");
+
+        self.lanes.push((name.to_string(), kind));
+        MockLane(self.lanes.len() - 1)
+    }
+
+    pub fn lane(&self, name: &str) -> Option<MockLane> {
+        self.lanes
+            .iter()
+            .position(|(lane_name, _)| lane_name == name)
+            .map(MockLane)
+    }
+
+    pub fn lane_kind(&self, lane: MockLane) -> MockLaneKind {
+        self.lanes[lane.0].1
+    }
+
+    /// Intercept all messages that are sent by the server from now on.
+    ///
+    /// Filters are applied in the order in which they were added, before the
+    /// messages reach the channel. Messages that are dropped by a filter do
+    /// not show up in the traffic statistics.
+    pub fn add_server_filter<F>(&mut self, filter: F) -> MockFilterId
+    where
+        F: Fn(&MockFilterContext, S) -> MockAction<S> + 'static,
+    {
+        let id = self.new_filter_id();
+        self.server_filters.add(id, Rc::new(filter));
+        id
+    }
+
+    /// Intercept all messages that are sent by clients from now on.
+    pub fn add_client_filter<F>(&mut self, filter: F) -> MockFilterId
+    where
+        F: Fn(&MockFilterContext, C) -> MockAction<C> + 'static,
+    {
+        let id = self.new_filter_id();
+        self.client_filters.add(id, Rc::new(filter));
+        id
+    }
+
+    /// Remove a filter. Returns false if there is no filter with this id.
+    pub fn remove_filter(&mut self, id: MockFilterId) -> bool {
+        self.server_filters.remove(id) || self.client_filters.remove(id)
+    }
+
+    fn new_filter_id(&mut self) -> MockFilterId {
+        let id = MockFilterId(self.next_filter_id);
+        self.next_filter_id += 1;
+        id
     }
 
     fn socket_mut(&mut self, player: PlayerId) -> &mut MockSocket<S, C> {
         self.sockets.get_mut(&player).expect("Unknown PlayerId")
     }
 
+    pub fn players(&self) -> impl Iterator<Item = PlayerId> + '_ {
+        self.sockets.keys().copied()
+    }
+
+    pub fn contains_player(&self, player: PlayerId) -> bool {
+        self.sockets.contains_key(&player)
+    }
+
+    pub fn is_connected(&self, player: PlayerId) -> bool {
+        self.sockets
+            .get(&player)
+            .is_some_and(|socket| socket.connected)
+    }
+
+    /// Add a socket for a new player, using perfect params.
+    pub fn add_player(&mut self, player: PlayerId) {
+        assert!(
+            !self.sockets.contains_key(&player),
+            "PlayerId already exists"
+        );
+
+        let epoch = self.new_epoch();
+        self.sockets
+            .insert(player, MockSocket::new(self.clock.clone(), epoch));
+    }
+
+    /// Remove the socket of a player. Messages that are in transit to or from
+    /// the player are lost.
+    pub fn remove_player(&mut self, player: PlayerId) {
+        self.sockets.remove(&player).expect("Unknown PlayerId");
+    }
+
+    /// Simulate a hard disconnect of a player.
+    ///
+    /// Messages that are in transit are lost, and messages that are sent to or
+    /// from the player are dropped until the player reconnects. The socket's
+    /// conditions are kept.
+    pub fn disconnect(&mut self, player: PlayerId) {
+        let epoch = self.new_epoch();
+        self.socket_mut(player).reset(false, epoch);
+    }
+
+    /// Reconnect a disconnected player, either under the same or under a new
+    /// `PlayerId`. The conditions of the old socket are kept.
+    pub fn reconnect(&mut self, player: PlayerId, new_player: PlayerId) {
+        let mut socket = self.sockets.remove(&player).expect("Unknown PlayerId");
+        assert!(
+            !self.sockets.contains_key(&new_player),
+            "PlayerId already exists"
+        );
+
+        socket.reset(true, self.new_epoch());
+        self.sockets.insert(new_player, socket);
+    }
+
+    fn new_epoch(&mut self) -> u64 {
+        let epoch = self.next_epoch;
+        self.next_epoch += 1;
+        epoch
+    }
+
     pub fn set_params(&mut self, player: PlayerId, params: MockSocketParams) {
-        self.socket_mut(player).params = params;
+        self.set_conditions(player, MockSocketConditions::constant(params));
+    }
+
+    pub fn set_conditions(&mut self, player: PlayerId, conditions: MockSocketConditions) {
+        self.socket_mut(player).conditions = conditions;
+    }
+
+    pub fn params(&self, player: PlayerId) -> MockSocketParams {
+        let socket = self.sockets.get(&player).expect("Unknown PlayerId");
+        socket.conditions.params_at(self.clock.local_time())
+    }
+
+    /// Returns the counters of messages sent to and from `player`.
+    ///
+    /// The counters are kept when the player disconnects or reconnects.
+    pub fn stats(&self, player: PlayerId) -> &MockSocketStats {
+        &self.sockets.get(&player).expect("Unknown PlayerId").stats
+    }
+
+    /// Reset the counters of all sockets. Messages that are still in transit
+    /// are counted as sent before the reset, but as dropped or delivered after
+    /// it.
+    pub fn reset_stats(&mut self) {
+        for socket in self.sockets.values_mut() {
+            socket.stats.reset();
+        }
+    }
+
+    /// Record the counters of all sockets as gauges named
+    /// `{prefix}_{player}_server_out_*` and `{prefix}_{player}_client_out_*`.
+    pub fn record_metrics(&self, prefix: &str, metrics: &mut Metrics) {
+        for (player, socket) in self.sockets.iter() {
+            socket
+                .stats
+                .record_metrics(&format!("{}_{}", prefix, player.0), metrics);
+        }
+    }
+
+    /// Returns the time at which the next message in transit arrives.
+    ///
+    /// This can be used to advance the clock directly to the next arrival in
+    /// a discrete-event simulation. Note that the message may turn out to be
+    /// discarded on arrival, e.g. if its receiver has disconnected in the
+    /// meantime.
+    pub fn next_arrival_time(&self) -> Option<LocalTime> {
+        self.in_transit.peek_time()
+    }
+
+    pub fn num_in_transit(&self) -> usize {
+        self.in_transit.len()
     }
 
+    /// Send a message on the default lane.
     pub fn send_to_server(&mut self, sender: PlayerId, message: C) {
-        let socket = self.socket_mut(sender);
-        socket.client_out.send(&socket.params.client_out, message);
+        self.send_to_server_on(MockLane::DEFAULT, sender, message);
     }
 
+    /// Send a message on the default lane.
     pub fn send_to_client(&mut self, receiver: PlayerId, message: S) {
-        let socket = self.socket_mut(receiver);
-        socket.server_out.send(&socket.params.server_out, message);
+        self.send_to_client_on(MockLane::DEFAULT, receiver, message);
     }
 
-    pub fn receive_from_server(&mut self, receiver: PlayerId) -> Vec<(LocalTime, S)> {
-        let socket = self.socket_mut(receiver);
-        let mut messages = Vec::new();
-        while let Some(message) = socket.server_out.receive() {
-            messages.push(message);
+    pub fn send_to_server_on(&mut self, lane: MockLane, sender: PlayerId, message: C) {
+        let context = MockFilterContext {
+            player: sender,
+            direction: MockDirection::ClientToServer,
+            lane,
+            send_time: self.clock.local_time(),
+        };
+
+        for (message, extra_delay) in self.client_filters.apply(&context, message) {
+            let size = (self.client_msg_size)(&message);
+            self.send(sender, lane, Payload::ToServer(message), size, extra_delay);
         }
+    }
 
-        messages
+    pub fn send_to_client_on(&mut self, lane: MockLane, receiver: PlayerId, message: S) {
+        let context = MockFilterContext {
+            player: receiver,
+            direction: MockDirection::ServerToClient,
+            lane,
+            send_time: self.clock.local_time(),
+        };
+
+        for (message, extra_delay) in self.server_filters.apply(&context, message) {
+            let size = (self.server_msg_size)(&message);
+            self.send(
+                receiver,
+                lane,
+                Payload::ToClient(message),
+                size,
+                extra_delay,
+            );
+        }
+    }
+
+    fn send(
+        &mut self,
+        player: PlayerId,
+        lane: MockLane,
+        payload: Payload<S, C>,
+        size: usize,
+        extra_delay: LocalDt,
+    ) {
+        let time = self.clock.local_time();
+        let direction = payload.direction();
+        let kind = self.lane_kind(lane);
+        let is_reliable = matches!(kind, MockLaneKind::ReliableOrdered { .. });
+
+        // Traces only cover unreliable lanes, since a recorded drop would
+        // break the guarantees of a reliable lane.
+        let replayed = if is_reliable {
+            None
+        } else {
+            self.next_residual(player, direction, lane)
+        };
+
+        let rng = &mut self.rng;
+        let socket = self.sockets.get_mut(&player).expect("Unknown PlayerId");
+        let residual = if socket.connected {
+            replayed.unwrap_or_else(|| socket.sample_residual(rng, direction, kind, time))
+        } else {
+            None
+        };
+        let epoch = socket.epoch;
+        let seq = socket.next_send_seq(direction, lane);
+
+        socket.stats_mut(direction).record_sent(size);
+        if residual.is_none() {
+            if is_reliable && socket.connected {
+                socket.abandon(direction, lane, seq);
+            } else {
+                socket.stats_mut(direction).record_dropped();
+            }
+        }
+
+        let arrival_time = residual.map(|residual| time + residual + extra_delay);
+        if let Some(arrival_time) = arrival_time {
+            self.in_transit.push(
+                arrival_time,
+                InTransit {
+                    player,
+                    epoch,
+                    lane,
+                    seq,
+                    send_time: time,
+                    size,
+                    payload,
+                },
+            );
+        }
+
+        if let Some(trace) = self.trace.as_mut() {
+            trace.record(MockTraceEntry {
+                player,
+                direction,
+                lane,
+                send_time: time,
+                arrival_time,
+                residual,
+                size,
+            });
+        }
+    }
+
+    fn next_residual(
+        &mut self,
+        player: PlayerId,
+        direction: MockDirection,
+        lane: MockLane,
+    ) -> Option<Option<LocalDt>> {
+        self.replay
+            .as_mut()
+            .and_then(|replay| replay.next_residual(player, direction, lane))
+    }
+
+    /// Move all messages that have arrived by now to the sockets.
+    fn deliver_arrived(&mut self) {
+        let now = self.clock.local_time();
+
+        while let Some((arrival_time, in_transit)) = self.in_transit.pop_due(now) {
+            let socket = match self.sockets.get_mut(&in_transit.player) {
+                Some(socket) => socket,
+                None => continue,
+            };
+
+            if socket.epoch != in_transit.epoch {
+                socket
+                    .stats_mut(in_transit.payload.direction())
+                    .record_dropped();
+                continue;
+            }
+
+            let kind = self.lanes[in_transit.lane.0].1;
+            socket.arrive(arrival_time, in_transit, kind);
+        }
+    }
+
+    pub fn receive_from_server(&mut self, receiver: PlayerId) -> Vec<(LocalTime, S)> {
+        self.deliver_arrived();
+
+        let socket = self.socket_mut(receiver);
+        let client_clock = &socket.client_clock;
+        let stats = &mut socket.stats.server_out;
+        socket
+            .server_out_arrived
+            .drain(..)
+            .map(|arrived| {
+                arrived.record_delivered(stats);
+                (
+                    client_clock.local_time_at(arrived.arrival_time),
+                    arrived.message,
+                )
+            })
+            .collect()
     }
 
     pub fn receive_from_clients(&mut self) -> Vec<(LocalTime, PlayerId, C)> {
+        self.deliver_arrived();
+
         let mut messages = Vec::new();
         for (sender, socket) in self.sockets.iter_mut() {
-            while let Some((receive_time, message)) = socket.client_out.receive() {
-                messages.push((receive_time, *sender, message));
+            for arrived in socket.client_out_arrived.drain(..) {
+                arrived.record_delivered(&mut socket.stats.client_out);
+                messages.push((arrived.arrival_time, *sender, arrived.message));
             }
         }
 
         messages.sort_by(|(time1, _, _), (time2, _, _)| time1.partial_cmp(time2).unwrap());
+
+        let server_clock = &self.server_clock;
         messages
+            .into_iter()
+            .map(|(receive_time, sender, message)| {
+                (server_clock.local_time_at(receive_time), sender, message)
+            })
+            .collect()
+    }
+}
+
+/// A client's view of a [`MockNet`], implementing [`ClientTransport`].
+pub struct MockClientTransport<'a, S, C> {
+    net: &'a mut MockNet<S, C>,
+    player: PlayerId,
+}
+
+impl<S, C> MockNet<S, C> {
+    /// Borrow the client end of the socket of `player`. The server end is
+    /// implemented by `MockNet` itself.
+    pub fn client(&mut self, player: PlayerId) -> MockClientTransport<'_, S, C> {
+        assert!(self.contains_player(player), "Unknown PlayerId");
+
+        MockClientTransport { net: self, player }
+    }
+}
+
+impl<S, C> ServerTransport<S, C> for MockNet<S, C> {
+    fn send(&mut self, receiver: PlayerId, message: S) {
+        self.send_to_client(receiver, message);
+    }
+
+    fn receive(&mut self) -> Vec<(LocalTime, PlayerId, C)> {
+        self.receive_from_clients()
+    }
+}
+
+impl<'a, S, C> ClientTransport<S, C> for MockClientTransport<'a, S, C> {
+    fn send(&mut self, message: C) {
+        self.net.send_to_server(self.player, message);
+    }
+
+    fn receive(&mut self) -> Vec<(LocalTime, S)> {
+        self.net.receive_from_server(self.player)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MockNet, MockSocketParams};
+    use crate::{
+        mock::{
+            MockAction, MockChannelParams, MockConditions, MockLane, MockLaneKind,
+            MockSocketConditions, MockTrace,
+        },
+        transport::{ClientTransport, ServerTransport},
+        LocalClock, LocalDt, LocalTime, PlayerId,
+    };
+
+    #[test]
+    fn test_disconnect_and_reconnect() {
+        let mut clock = LocalClock::new();
+        let mut net: MockNet<u32, u32> = MockNet::new(&[PlayerId(0)], clock.clone());
+
+        net.send_to_client(PlayerId(0), 1);
+        net.send_to_server(PlayerId(0), 2);
+        net.disconnect(PlayerId(0));
+        net.send_to_client(PlayerId(0), 3);
+
+        clock.advance(LocalDt::from_secs(1.0));
+        assert!(net.receive_from_server(PlayerId(0)).is_empty());
+        assert!(net.receive_from_clients().is_empty());
+
+        net.reconnect(PlayerId(0), PlayerId(1));
+        assert!(!net.contains_player(PlayerId(0)));
+        assert!(net.is_connected(PlayerId(1)));
+
+        net.send_to_server(PlayerId(1), 4);
+        clock.advance(LocalDt::from_secs(1.0));
+        let messages = net.receive_from_clients();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].1, PlayerId(1));
+        assert_eq!(messages[0].2, 4);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut clock = LocalClock::new();
+        let mut net: MockNet<u32, u32> = MockNet::new(&[PlayerId(0)], clock.clone());
+        net.set_msg_size_fns(|_| 100, |_| 10);
+        net.set_params(
+            PlayerId(0),
+            MockSocketParams {
+                server_out: MockChannelParams {
+                    latency_mean: LocalDt::from_millis(50.0),
+                    ..MockChannelParams::perfect()
+                },
+                client_out: MockChannelParams {
+                    loss: 1.0,
+                    ..MockChannelParams::perfect()
+                },
+            },
+        );
+
+        net.send_to_client(PlayerId(0), 1);
+        net.send_to_client(PlayerId(0), 2);
+        net.send_to_server(PlayerId(0), 3);
+        assert_eq!(net.stats(PlayerId(0)).server_out.num_in_transit(), 2);
+
+        clock.advance(LocalDt::from_secs(1.0));
+        net.receive_from_server(PlayerId(0));
+
+        let stats = net.stats(PlayerId(0));
+        assert_eq!(stats.server_out.num_delivered, 2);
+        assert_eq!(stats.server_out.bytes_delivered, 200);
+        assert_eq!(stats.server_out.loss(), Some(0.0));
+        assert_eq!(
+            stats.server_out.latency_max,
+            Some(LocalDt::from_millis(50.0))
+        );
+        assert_eq!(stats.client_out.num_dropped, 1);
+        assert_eq!(stats.client_out.bytes_sent, 10);
+        assert_eq!(stats.client_out.loss(), Some(1.0));
+    }
+
+    #[test]
+    fn test_reset_stats_in_transit() {
+        let mut clock = LocalClock::new();
+        let mut net: MockNet<u32, u32> = MockNet::new(&[PlayerId(0)], clock.clone());
+        let channel = MockChannelParams {
+            latency_mean: LocalDt::from_millis(50.0),
+            ..MockChannelParams::perfect()
+        };
+        net.set_conditions(
+            PlayerId(0),
+            MockSocketConditions::symmetric(MockConditions::constant(channel)),
+        );
+
+        net.send_to_client(PlayerId(0), 1);
+        net.send_to_client(PlayerId(0), 2);
+        net.reset_stats();
+        assert_eq!(net.stats(PlayerId(0)).server_out.num_sent, 0);
+        assert_eq!(net.stats(PlayerId(0)).server_out.num_in_transit(), 2);
+
+        // One message is delivered and the other one is discarded because its
+        // receiver disconnects.
+        clock.advance(LocalDt::from_secs(1.0));
+        net.receive_from_server(PlayerId(0));
+        net.send_to_client(PlayerId(0), 3);
+        net.disconnect(PlayerId(0));
+        clock.advance(LocalDt::from_secs(1.0));
+        net.receive_from_clients();
+
+        let stats = &net.stats(PlayerId(0)).server_out;
+        assert_eq!(stats.num_sent, 1);
+        assert_eq!(stats.num_delivered, 2);
+        assert_eq!(stats.num_dropped, 1);
+        assert_eq!(stats.num_in_transit(), 0);
+    }
+
+    #[test]
+    fn test_filters() {
+        let mut clock = LocalClock::new();
+        let mut net: MockNet<u32, u32> = MockNet::new(&[PlayerId(0), PlayerId(1)], clock.clone());
+
+        // Drop the snapshot for tick 30, and send tick 31 twice.
+        net.add_server_filter(|_, tick| match tick {
+            30 => MockAction::Drop,
+            31 => MockAction::Duplicate(vec![tick, tick]),
+            _ => MockAction::Pass(tick),
+        });
+
+        // Delay all inputs from player 1 by 500ms for two seconds.
+        let delay_filter = net.add_client_filter(|context, input| {
+            let time = context.send_time.to_secs();
+            if context.player == PlayerId(1) && (1.0..3.0).contains(&time) {
+                MockAction::Delay(input, LocalDt::from_millis(500.0))
+            } else {
+                MockAction::Pass(input)
+            }
+        });
+
+        for tick in 29..=32 {
+            net.send_to_client(PlayerId(0), tick);
+        }
+        clock.advance(LocalDt::from_secs(1.0));
+        let ticks: Vec<_> = net
+            .receive_from_server(PlayerId(0))
+            .into_iter()
+            .map(|(_, tick)| tick)
+            .collect();
+        assert_eq!(ticks, vec![29, 31, 31, 32]);
+
+        net.send_to_server(PlayerId(0), 1);
+        net.send_to_server(PlayerId(1), 2);
+        clock.advance(LocalDt::from_millis(100.0));
+        let inputs = net.receive_from_clients();
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].1, PlayerId(0));
+
+        clock.advance(LocalDt::from_millis(400.0));
+        let inputs = net.receive_from_clients();
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].1, PlayerId(1));
+
+        assert!(net.remove_filter(delay_filter));
+        assert!(!net.remove_filter(delay_filter));
+    }
+
+    #[test]
+    fn test_lanes() {
+        let mut clock = LocalClock::new();
+        let mut net: MockNet<u32, u32> = MockNet::new(&[PlayerId(0)], clock.clone());
+        let reliable = net.add_lane(
+            "reliable",
+            MockLaneKind::ReliableOrdered {
+                retransmit_delay: LocalDt::from_millis(100.0),
+            },
+        );
+        let sequenced = net.add_lane("sequenced", MockLaneKind::UnreliableSequenced);
+
+        let channel = MockChannelParams {
+            latency_mean: LocalDt::from_millis(10.0),
+            ..MockChannelParams::perfect()
+        };
+        let conditions =
+            MockConditions::constant(channel).outage(LocalTime::zero(), LocalDt::from_millis(50.0));
+        net.set_conditions(PlayerId(0), MockSocketConditions::symmetric(conditions));
+
+        let mut receive = |net: &mut MockNet<u32, u32>, dt: f64| {
+            clock.advance(LocalDt::from_millis(dt));
+            net.receive_from_server(PlayerId(0))
+                .into_iter()
+                .map(|(_, message)| message)
+                .collect::<Vec<_>>()
+        };
+
+        // The first reliable message is lost in the outage and retransmitted
+        // at 100ms. The second one has to wait for it.
+        net.send_to_client_on(reliable, PlayerId(0), 1);
+        assert!(receive(&mut net, 60.0).is_empty());
+        net.send_to_client_on(reliable, PlayerId(0), 2);
+        net.send_to_client(PlayerId(0), 3);
+        assert_eq!(receive(&mut net, 20.0), vec![3]);
+        assert_eq!(receive(&mut net, 40.0), vec![1, 2]);
+
+        // A sequenced message that is overtaken by a newer one is discarded.
+        net.add_server_filter(move |context, message| {
+            if context.lane == sequenced && message == 4 {
+                MockAction::Delay(message, LocalDt::from_millis(100.0))
+            } else {
+                MockAction::Pass(message)
+            }
+        });
+        net.send_to_client_on(sequenced, PlayerId(0), 4);
+        net.send_to_client_on(sequenced, PlayerId(0), 5);
+        assert_eq!(receive(&mut net, 1000.0), vec![5]);
+        assert_eq!(net.stats(PlayerId(0)).server_out.num_dropped, 1);
+    }
+
+    #[test]
+    fn test_reliable_disconnect() {
+        let mut clock = LocalClock::new();
+        let mut net: MockNet<u32, u32> = MockNet::new(&[PlayerId(0)], clock.clone());
+        let reliable = net.add_lane(
+            "reliable",
+            MockLaneKind::ReliableOrdered {
+                retransmit_delay: LocalDt::from_millis(100.0),
+            },
+        );
+
+        let channel = MockChannelParams {
+            latency_mean: LocalDt::from_millis(10.0),
+            ..MockChannelParams::perfect()
+        };
+        let conditions =
+            MockConditions::constant(channel).outage(LocalTime::zero(), LocalDt::from_millis(50.0));
+        net.set_conditions(PlayerId(0), MockSocketConditions::symmetric(conditions));
+
+        // Message 1 is retransmitted, message 2 is held back waiting for it and
+        // message 3 has arrived, but has not been received yet.
+        net.send_to_client_on(reliable, PlayerId(0), 1);
+        clock.advance(LocalDt::from_millis(60.0));
+        net.send_to_client_on(reliable, PlayerId(0), 2);
+        net.send_to_client(PlayerId(0), 3);
+        clock.advance(LocalDt::from_millis(20.0));
+        net.receive_from_clients();
+
+        net.disconnect(PlayerId(0));
+        let stats = &net.stats(PlayerId(0)).server_out;
+        assert_eq!(stats.num_dropped, 2);
+        assert_eq!(stats.num_in_transit(), 1);
+
+        clock.advance(LocalDt::from_secs(1.0));
+        assert!(net.receive_from_server(PlayerId(0)).is_empty());
+        let stats = &net.stats(PlayerId(0)).server_out;
+        assert_eq!(stats.num_dropped, 3);
+        assert_eq!(stats.num_in_transit(), 0);
+        assert_eq!(stats.loss(), Some(1.0));
+
+        // The counters survive reconnecting.
+        net.reconnect(PlayerId(0), PlayerId(1));
+        net.send_to_client_on(reliable, PlayerId(1), 4);
+        clock.advance(LocalDt::from_secs(1.0));
+        assert_eq!(net.receive_from_server(PlayerId(1)).len(), 1);
+        let stats = &net.stats(PlayerId(1)).server_out;
+        assert_eq!(stats.num_sent, 4);
+        assert_eq!(stats.num_delivered, 1);
+        assert_eq!(stats.num_in_transit(), 0);
+    }
+
+    #[test]
+    fn test_reliable_outage() {
+        let mut clock = LocalClock::new();
+        let mut net: MockNet<u32, u32> = MockNet::new(&[PlayerId(0)], clock.clone());
+        let reliable = net.add_lane(
+            "reliable",
+            MockLaneKind::ReliableOrdered {
+                retransmit_delay: LocalDt::from_millis(10.0),
+            },
+        );
+
+        // The first outage spans many retransmissions. The second one outlasts
+        // all of them, so that the message sent during it is abandoned.
+        let conditions = MockConditions::constant(MockChannelParams::perfect())
+            .outage(LocalTime::zero(), LocalDt::from_secs(2.0))
+            .outage(LocalTime::from_secs(10.0), LocalDt::from_secs(100.0));
+        net.set_conditions(
+            PlayerId(0),
+            MockSocketConditions {
+                server_out: conditions,
+                client_out: MockConditions::constant(MockChannelParams::perfect()),
+            },
+        );
+
+        let mut receive = |net: &mut MockNet<u32, u32>, secs: f64| {
+            clock.advance(LocalDt::from_secs(secs));
+            net.receive_from_server(PlayerId(0))
+                .into_iter()
+                .map(|(_, message)| message)
+                .collect::<Vec<_>>()
+        };
+
+        net.send_to_client_on(reliable, PlayerId(0), 1);
+        assert!(receive(&mut net, 1.0).is_empty());
+        net.send_to_client_on(reliable, PlayerId(0), 2);
+        assert_eq!(receive(&mut net, 2.0), vec![1, 2]);
+
+        assert!(receive(&mut net, 7.0).is_empty());
+        net.send_to_client_on(reliable, PlayerId(0), 3);
+        assert_eq!(net.stats(PlayerId(0)).server_out.num_abandoned, 1);
+
+        // Once the channel works again, later messages are not held back by
+        // the abandoned one.
+        net.set_params(PlayerId(0), MockSocketParams::perfect());
+        net.send_to_client_on(reliable, PlayerId(0), 4);
+        assert_eq!(receive(&mut net, 1.0), vec![4]);
+    }
+
+    #[test]
+    fn test_record_replay() {
+        let run = |params: MockSocketParams, trace: Option<&MockTrace>| {
+            let mut clock = LocalClock::new();
+            let mut net: MockNet<u32, u32> = MockNet::new(&[PlayerId(0)], clock.clone());
+            let reliable = net.add_lane(
+                "reliable",
+                MockLaneKind::ReliableOrdered {
+                    retransmit_delay: LocalDt::from_millis(50.0),
+                },
+            );
+            net.set_params(PlayerId(0), params);
+            net.add_server_filter(|_, message| {
+                if message % 3 == 0 {
+                    MockAction::Delay(message, LocalDt::from_millis(20.0))
+                } else {
+                    MockAction::Pass(message)
+                }
+            });
+
+            net.start_recording();
+            if let Some(trace) = trace {
+                net.replay(trace);
+            }
+
+            let mut received = Vec::new();
+            for i in 0..1000 {
+                net.send_to_client(PlayerId(0), i);
+                net.send_to_client_on(reliable, PlayerId(0), 1_000_000 + i);
+                clock.advance(LocalDt::from_millis(10.0));
+
+                received.extend(
+                    net.receive_from_server(PlayerId(0))
+                        .into_iter()
+                        .filter(|(_, message)| *message < 1_000_000),
+                );
+            }
+
+            (net.take_trace().unwrap(), received)
+        };
+
+        let lossy = MockChannelParams {
+            latency_mean: LocalDt::from_millis(30.0),
+            latency_std_dev: LocalDt::from_millis(10.0),
+            loss: 0.3,
+            ..MockChannelParams::perfect()
+        };
+        let params = MockSocketParams {
+            server_out: lossy.clone(),
+            client_out: lossy,
+        };
+        let (trace, received) = run(params, None);
+        let (replayed_trace, replayed_received) = run(MockSocketParams::perfect(), Some(&trace));
+
+        // The unreliable lane behaves exactly as recorded, even though the
+        // filter delay is applied again. The reliable lane is not replayed.
+        let unreliable = |trace: &MockTrace| {
+            trace
+                .entries()
+                .iter()
+                .filter(|entry| entry.lane == MockLane(0))
+                .map(|entry| (entry.send_time, entry.arrival_time))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(unreliable(&trace), unreliable(&replayed_trace));
+        assert!(unreliable(&trace)
+            .iter()
+            .any(|(_, arrival)| arrival.is_none()));
+        assert_eq!(received, replayed_received);
+        assert!(replayed_trace
+            .entries()
+            .iter()
+            .filter(|entry| entry.lane != MockLane(0))
+            .all(|entry| entry.arrival_time.is_some()));
+    }
+
+    #[test]
+    fn test_transport() {
+        fn echo(server: &mut dyn ServerTransport<u32, u32>) {
+            for (_, sender, message) in server.receive() {
+                server.send(sender, message + sender.to_u32());
+            }
+        }
+
+        fn request(client: &mut dyn ClientTransport<u32, u32>, message: u32) {
+            client.send(message);
+        }
+
+        fn receive(client: &mut dyn ClientTransport<u32, u32>) -> Vec<u32> {
+            client
+                .receive()
+                .into_iter()
+                .map(|(_, message)| message)
+                .collect()
+        }
+
+        let mut clock = LocalClock::new();
+        let mut net: MockNet<u32, u32> = MockNet::new(&[PlayerId(0), PlayerId(1)], clock.clone());
+        let params = MockChannelParams {
+            latency_mean: LocalDt::from_millis(10.0),
+            ..MockChannelParams::perfect()
+        };
+        for player in &[PlayerId(0), PlayerId(1)] {
+            net.set_params(
+                *player,
+                MockSocketParams {
+                    server_out: params.clone(),
+                    client_out: params.clone(),
+                },
+            );
+        }
+
+        request(&mut net.client(PlayerId(0)), 10);
+        request(&mut net.client(PlayerId(1)), 20);
+        echo(&mut net);
+        assert!(receive(&mut net.client(PlayerId(0))).is_empty());
+
+        clock.advance(LocalDt::from_millis(15.0));
+        echo(&mut net);
+        clock.advance(LocalDt::from_millis(15.0));
+        assert_eq!(receive(&mut net.client(PlayerId(0))), vec![10]);
+        assert_eq!(receive(&mut net.client(PlayerId(1))), vec![21]);
+    }
+
+    #[test]
+    fn test_late_join() {
+        let mut clock = LocalClock::new();
+        let mut net: MockNet<u32, u32> = MockNet::new(&[], clock.clone());
+
+        net.add_player(PlayerId(3));
+        net.send_to_client(PlayerId(3), 7);
+        clock.advance(LocalDt::from_secs(1.0));
+
+        let messages = net.receive_from_server(PlayerId(3));
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].1, 7);
+
+        net.remove_player(PlayerId(3));
+        assert_eq!(net.players().count(), 0);
     }
 }
@@ -0,0 +1,135 @@
+use crate::{LocalDt, Metrics};
+
+/// Counters for the messages that were sent in one direction of a
+/// [`MockSocket`](super::MockSocket).
+///
+/// Latencies are one-way and measured on the root clock of the
+/// [`MockNet`](super::MockNet), so they are not affected by clock skew.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockDirectionStats {
+    pub num_sent: usize,
+
+    /// Messages that were lost by the channel, or discarded because their
+    /// receiver was disconnected before receiving them.
+    pub num_dropped: usize,
+
+    /// Messages that were received by the other end.
+    pub num_delivered: usize,
+
+    /// Reliable messages that were given up on after too many failed
+    /// retransmissions. These are also counted in `num_dropped`.
+    pub num_abandoned: usize,
+
+    pub bytes_sent: usize,
+    pub bytes_delivered: usize,
+
+    pub latency_min: Option<LocalDt>,
+    pub latency_max: Option<LocalDt>,
+    latency_sum: LocalDt,
+
+    // Tracked separately from the other counters, so that it stays correct
+    // when they are reset while messages are in transit.
+    num_in_transit: usize,
+}
+
+impl MockDirectionStats {
+    pub fn num_in_transit(&self) -> usize {
+        self.num_in_transit
+    }
+
+    /// Reset all counters except for the number of messages in transit.
+    pub(crate) fn reset(&mut self) {
+        *self = Self {
+            num_in_transit: self.num_in_transit,
+            ..Self::default()
+        };
+    }
+
+    /// Fraction of the messages that are no longer in transit and that were
+    /// dropped.
+    pub fn loss(&self) -> Option<f64> {
+        let num_done = self.num_dropped + self.num_delivered;
+
+        if num_done > 0 {
+            Some(self.num_dropped as f64 / num_done as f64)
+        } else {
+            None
+        }
+    }
+
+    pub fn latency_mean(&self) -> Option<LocalDt> {
+        if self.num_delivered > 0 {
+            Some(self.latency_sum / self.num_delivered as f64)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn record_sent(&mut self, size: usize) {
+        self.num_sent += 1;
+        self.num_in_transit += 1;
+        self.bytes_sent += size;
+    }
+
+    pub(crate) fn record_dropped(&mut self) {
+        self.num_dropped += 1;
+        self.num_in_transit -= 1;
+    }
+
+    pub(crate) fn record_abandoned(&mut self) {
+        self.record_dropped();
+        self.num_abandoned += 1;
+    }
+
+    pub(crate) fn record_delivered(&mut self, size: usize, latency: LocalDt) {
+        self.num_delivered += 1;
+        self.num_in_transit -= 1;
+        self.bytes_delivered += size;
+        self.latency_sum += latency;
+        self.latency_min = Some(self.latency_min.map_or(latency, |min| min.min(latency)));
+        self.latency_max = Some(self.latency_max.map_or(latency, |max| max.max(latency)));
+    }
+
+    pub fn record_metrics(&self, prefix: &str, metrics: &mut Metrics) {
+        metrics.record_gauge(&format!("{}_num_sent", prefix), self.num_sent as f64);
+        metrics.record_gauge(&format!("{}_num_dropped", prefix), self.num_dropped as f64);
+        metrics.record_gauge(
+            &format!("{}_num_delivered", prefix),
+            self.num_delivered as f64,
+        );
+        metrics.record_gauge(&format!("{}_bytes_sent", prefix), self.bytes_sent as f64);
+
+        if let Some(loss) = self.loss() {
+            metrics.record_gauge(&format!("{}_loss", prefix), loss);
+        }
+        if let (Some(min), Some(mean), Some(max)) =
+            (self.latency_min, self.latency_mean(), self.latency_max)
+        {
+            metrics.record_gauge(&format!("{}_latency_min", prefix), min.to_secs());
+            metrics.record_gauge(&format!("{}_latency_mean", prefix), mean.to_secs());
+            metrics.record_gauge(&format!("{}_latency_max", prefix), max.to_secs());
+        }
+    }
+}
+
+/// Counters for both directions of a [`MockSocket`](super::MockSocket).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MockSocketStats {
+    pub server_out: MockDirectionStats,
+    pub client_out: MockDirectionStats,
+}
+
+impl MockSocketStats {
+    pub(crate) fn reset(&mut self) {
+        self.server_out.reset();
+        self.client_out.reset();
+    }
+
+    /// Record gauges named `{prefix}_server_out_*` and `{prefix}_client_out_*`.
+    pub fn record_metrics(&self, prefix: &str, metrics: &mut Metrics) {
+        self.server_out
+            .record_metrics(&format!("{}_server_out", prefix), metrics);
+        self.client_out
+            .record_metrics(&format!("{}_client_out", prefix), metrics);
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{LocalDt, LocalTime, PlayerId};
+
+use super::MockLane;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MockDirection {
+    ServerToClient,
+    ClientToServer,
+}
+
+/// The fate of a single message that was sent through a
+/// [`MockNet`](super::MockNet).
+#[derive(Debug, Clone)]
+pub struct MockTraceEntry {
+    pub player: PlayerId,
+    pub direction: MockDirection,
+    pub lane: MockLane,
+    pub send_time: LocalTime,
+
+    /// The time at which the message arrives, or `None` if it was dropped.
+    pub arrival_time: Option<LocalTime>,
+
+    /// The time that the message spent in the network, excluding delay that
+    /// was added by filters, or `None` if it was dropped.
+    pub residual: Option<LocalDt>,
+
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MockTrace {
+    entries: Vec<MockTraceEntry>,
+}
+
+impl MockTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &[MockTraceEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn record(&mut self, entry: MockTraceEntry) {
+        self.entries.push(entry);
+    }
+}
+
+/// Replays the delivery schedule of a [`MockTrace`].
+///
+/// The `n`-th message that is sent on some lane in some direction of some
+/// socket gets the same residual (or is dropped) as the `n`-th message that
+/// was recorded for that lane and direction. The payloads themselves are not
+/// part of the trace, so they can differ between the recorded and the
+/// replayed run. Filters are applied again on top of the replayed residuals.
+///
+/// Reliable lanes are not replayed, since their messages can not be dropped.
+#[derive(Debug, Clone)]
+pub struct MockReplay {
+    residuals: BTreeMap<(PlayerId, MockDirection, MockLane), VecDeque<Option<LocalDt>>>,
+}
+
+impl MockReplay {
+    pub fn new(trace: &MockTrace) -> Self {
+        let mut residuals: BTreeMap<_, VecDeque<_>> = BTreeMap::new();
+
+        for entry in trace.entries() {
+            residuals
+                .entry((entry.player, entry.direction, entry.lane))
+                .or_default()
+                .push_back(entry.residual);
+        }
+
+        Self { residuals }
+    }
+
+    /// Returns the recorded outcome of the next message, or `None` if the
+    /// trace has no more messages for this lane and direction.
+    pub fn next_residual(
+        &mut self,
+        player: PlayerId,
+        direction: MockDirection,
+        lane: MockLane,
+    ) -> Option<Option<LocalDt>> {
+        self.residuals
+            .get_mut(&(player, direction, lane))
+            .and_then(|residuals| residuals.pop_front())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.residuals
+            .values()
+            .all(|residuals| residuals.is_empty())
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::VecDeque;
+
+use crate::{
+    codec::{BitReader, BitWriter, CodecError, NetSerialize},
+    GameDt, LocalClock, LocalDt, LocalTime, Metrics,
+};
+
+use super::RttEstimator;
+
+/// Number of packets before the latest one that are acknowledged by every
+/// header.
+pub const ACK_BITS: u16 = 32;
+
+/// Weight of each resolved packet in the smoothed loss estimate.
+const LOSS_SMOOTHING: f64 = 0.01;
+
+/// Probability with which [`LinkEstimate::playback_delay`] may run out of
+/// packets because too many consecutive ones were lost.
+const STARVATION_PROBABILITY: f64 = 0.01;
+
+/// Maximum number of packets in flight. When more packets are sent, the
+/// oldest ones are considered lost, so that sequence numbers can not wrap
+/// around while packets are in flight.
+const MAX_IN_FLIGHT: usize = 1024;
+
+/// Returns true if `a` is newer than `b`, taking wraparound into account.
+fn is_newer(a: u16, b: u16) -> bool {
+    a != b && a.wrapping_sub(b) < 1 << 15
+}
+
+/// Header that is prepended to every packet of a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketHeader {
+    pub seq: u16,
+
+    /// The newest sequence number received from the peer, if any.
+    pub ack: Option<u16>,
+
+    /// Bit `i` is set if packet `ack - 1 - i` has been received.
+    pub ack_bits: u32,
+}
+
+impl NetSerialize for PacketHeader {
+    fn serialize(&self, writer: &mut BitWriter) {
+        self.seq.serialize(writer);
+        self.ack.serialize(writer);
+        writer.write_bits(self.ack_bits as u64, 32);
+    }
+
+    fn deserialize(reader: &mut BitReader<'_>) -> Result<Self, CodecError> {
+        Ok(PacketHeader {
+            seq: u16::deserialize(reader)?,
+            ack: Option::deserialize(reader)?,
+            ack_bits: reader.read_bits(32)? as u32,
+        })
+    }
+}
+
+/// The fate of a packet that has been sent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PacketNotification {
+    Delivered { seq: u16, rtt: LocalDt },
+    Lost { seq: u16 },
+}
+
+/// A snapshot of the link quality, e.g. for adapting the playback delay or
+/// the send rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkEstimate {
+    pub rtt: Option<LocalDt>,
+    pub rtt_var: LocalDt,
+    pub loss: f64,
+}
+
+impl LinkEstimate {
+    /// Returns a playback delay for a stream that is received over this link
+    /// and has one packet every `send_dt`.
+    ///
+    /// On top of the two packets that are needed for interpolation, the
+    /// delay covers the jitter of the link, as well as runs of consecutive
+    /// losses that are not too unlikely. Returns `None` if there are no RTT
+    /// samples yet.
+    pub fn playback_delay(&self, send_dt: GameDt) -> Option<GameDt> {
+        self.rtt?;
+
+        let loss = self.loss.min(0.99);
+        let num_lost = if loss > 0.0 {
+            (STARVATION_PROBABILITY.ln() / loss.ln()).ceil() - 1.0
+        } else {
+            0.0
+        };
+
+        Some(send_dt * (2.0 + num_lost.max(0.0)) + self.rtt_var.to_game_dt() * 2.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SentPacket {
+    seq: u16,
+    send_time: LocalTime,
+}
+
+/// Tracks acknowledgements of packets sent over an unreliable transport.
+///
+/// Every outgoing packet gets a header from
+/// [`send_header`](PacketAcks::send_header), and the header of every
+/// incoming packet is passed to
+/// [`receive_header`](PacketAcks::receive_header). Since each header
+/// acknowledges the latest [`ACK_BITS`] + 1 packets received from the peer,
+/// acks are redundant and no separate ack packets are needed.
+///
+/// A packet counts as lost once it can no longer be acknowledged, or once it
+/// has been in flight for longer than the maximum timeout of the
+/// [`RttEstimator`].
+#[derive(Debug, Clone)]
+pub struct PacketAcks {
+    clock: LocalClock,
+    rtt: RttEstimator,
+    loss: f64,
+
+    next_seq: u16,
+    sent: VecDeque<SentPacket>,
+    num_sent: usize,
+    num_delivered: usize,
+    num_lost: usize,
+
+    remote_seq: Option<u16>,
+    remote_bits: u32,
+
+    notifications: Vec<PacketNotification>,
+}
+
+impl PacketAcks {
+    pub fn new(clock: LocalClock) -> Self {
+        Self::with_rtt_estimator(RttEstimator::default(), clock)
+    }
+
+    pub fn with_rtt_estimator(rtt: RttEstimator, clock: LocalClock) -> Self {
+        Self {
+            clock,
+            rtt,
+            loss: 0.0,
+            next_seq: 0,
+            sent: VecDeque::new(),
+            num_sent: 0,
+            num_delivered: 0,
+            num_lost: 0,
+            remote_seq: None,
+            remote_bits: 0,
+            notifications: Vec::new(),
+        }
+    }
+
+    pub fn rtt(&self) -> &RttEstimator {
+        &self.rtt
+    }
+
+    /// Smoothed fraction of packets that are lost.
+    pub fn loss(&self) -> f64 {
+        self.loss
+    }
+
+    pub fn estimate(&self) -> LinkEstimate {
+        LinkEstimate {
+            rtt: self.rtt.smoothed_rtt(),
+            rtt_var: self.rtt.rtt_var(),
+            loss: self.loss,
+        }
+    }
+
+    pub fn num_sent(&self) -> usize {
+        self.num_sent
+    }
+
+    pub fn num_delivered(&self) -> usize {
+        self.num_delivered
+    }
+
+    pub fn num_lost(&self) -> usize {
+        self.num_lost
+    }
+
+    pub fn num_in_flight(&self) -> usize {
+        self.sent.len()
+    }
+
+    /// Returns the header for the next outgoing packet.
+    pub fn send_header(&mut self) -> PacketHeader {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+
+        if self.sent.len() >= MAX_IN_FLIGHT {
+            let oldest = self.sent.pop_front().unwrap();
+            self.record_lost(oldest.seq);
+        }
+
+        self.sent.push_back(SentPacket {
+            seq,
+            send_time: self.clock.local_time(),
+        });
+        self.num_sent += 1;
+
+        PacketHeader {
+            seq,
+            ack: self.remote_seq,
+            ack_bits: self.remote_bits,
+        }
+    }
+
+    /// Handle the header of an incoming packet. Returns false if the packet
+    /// is a duplicate or too old to be acknowledged, in which case it should
+    /// be discarded.
+    pub fn receive_header(&mut self, header: PacketHeader) -> bool {
+        let is_new = self.record_received(header.seq);
+
+        if let Some(ack) = header.ack {
+            self.process_acks(ack, header.ack_bits);
+        }
+
+        is_new
+    }
+
+    /// Returns the packets that have been delivered or lost since the last
+    /// call.
+    pub fn poll_notifications(&mut self) -> Vec<PacketNotification> {
+        let now = self.clock.local_time();
+
+        while let Some(oldest) = self.sent.front() {
+            if now - oldest.send_time <= self.rtt.max_rto() {
+                break;
+            }
+
+            let seq = oldest.seq;
+            self.sent.pop_front();
+            self.record_lost(seq);
+        }
+
+        std::mem::take(&mut self.notifications)
+    }
+
+    pub fn record_metrics(&self, prefix: &str, metrics: &mut Metrics) {
+        if let Some(rtt) = self.rtt.smoothed_rtt() {
+            metrics.record_gauge(&format!("{}_rtt", prefix), rtt.to_secs());
+            metrics.record_gauge(&format!("{}_rtt_var", prefix), self.rtt.rtt_var().to_secs());
+        }
+        metrics.record_gauge(&format!("{}_loss", prefix), self.loss);
+        metrics.record_gauge(
+            &format!("{}_num_in_flight", prefix),
+            self.num_in_flight() as f64,
+        );
+    }
+
+    fn record_received(&mut self, seq: u16) -> bool {
+        let latest = match self.remote_seq {
+            Some(latest) => latest,
+            None => {
+                self.remote_seq = Some(seq);
+                return true;
+            }
+        };
+
+        if is_newer(seq, latest) {
+            let shift = seq.wrapping_sub(latest) as u32;
+
+            // The previous latest packet moves into the bitfield.
+            self.remote_bits = self.remote_bits.checked_shl(shift).unwrap_or(0)
+                | 1u32.checked_shl(shift - 1).unwrap_or(0);
+            self.remote_seq = Some(seq);
+
+            true
+        } else {
+            let age = latest.wrapping_sub(seq);
+            if age == 0 || age > ACK_BITS {
+                return false;
+            }
+
+            let bit = 1 << (age - 1);
+            let is_new = self.remote_bits & bit == 0;
+            self.remote_bits |= bit;
+
+            is_new
+        }
+    }
+
+    fn process_acks(&mut self, ack: u16, ack_bits: u32) {
+        let now = self.clock.local_time();
+        let mut i = 0;
+
+        while i < self.sent.len() {
+            let seq = self.sent[i].seq;
+
+            if is_newer(seq, ack) {
+                // Newer packets are not covered by this header.
+                break;
+            }
+
+            let age = ack.wrapping_sub(seq);
+            let is_acked = age == 0 || (age <= ACK_BITS && ack_bits & (1 << (age - 1)) != 0);
+
+            if is_acked {
+                let sent = self.sent.remove(i).unwrap();
+                let rtt = now - sent.send_time;
+
+                self.rtt.record_sample(rtt);
+                self.num_delivered += 1;
+                self.update_loss(false);
+                self.notifications
+                    .push(PacketNotification::Delivered { seq, rtt });
+            } else if age > ACK_BITS {
+                self.sent.remove(i);
+                self.record_lost(seq);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    fn record_lost(&mut self, seq: u16) {
+        self.num_lost += 1;
+        self.update_loss(true);
+        self.notifications.push(PacketNotification::Lost { seq });
+    }
+
+    fn update_loss(&mut self, is_lost: bool) {
+        let sample = if is_lost { 1.0 } else { 0.0 };
+        self.loss += (sample - self.loss) * LOSS_SMOOTHING;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::{LinkEstimate, PacketAcks, PacketHeader, PacketNotification};
+    use crate::{
+        codec::{decode, encode},
+        mock::{MockChannel, MockChannelParams},
+        GameDt, LocalClock, LocalDt, Metrics,
+    };
+
+    #[test]
+    fn test_lossy_link() {
+        let mut clock = LocalClock::new();
+        let params = MockChannelParams {
+            latency_mean: LocalDt::from_millis(50.0),
+            loss: 0.2,
+            ..MockChannelParams::perfect()
+        };
+
+        let mut a = PacketAcks::new(clock.clone());
+        let mut b = PacketAcks::new(clock.clone());
+        let mut a_to_b = MockChannel::new(clock.clone());
+        let mut b_to_a = MockChannel::new(clock.clone());
+        let mut received_by_b = BTreeSet::new();
+        let mut notifications = Vec::new();
+        let mut loss_sum = 0.0;
+
+        // Go beyond 2^16 packets to check that wraparound is handled.
+        for i in 0..70_000 {
+            a_to_b.send(&params, encode(&a.send_header()));
+            b_to_a.send(&params, encode(&b.send_header()));
+
+            clock.advance(LocalDt::from_millis(10.0));
+
+            while let Some((_, bytes)) = a_to_b.receive() {
+                let header: PacketHeader = decode(&bytes).unwrap();
+                assert!(b.receive_header(header));
+                received_by_b.insert(header.seq);
+            }
+            while let Some((_, bytes)) = b_to_a.receive() {
+                a.receive_header(decode(&bytes).unwrap());
+            }
+
+            // Notifications must match what actually happened to the packets.
+            for notification in a.poll_notifications() {
+                match notification {
+                    PacketNotification::Delivered { seq, .. } => {
+                        assert!(received_by_b.remove(&seq))
+                    }
+                    PacketNotification::Lost { seq } => assert!(!received_by_b.contains(&seq)),
+                }
+                notifications.push(notification);
+            }
+
+            if i >= 10_000 {
+                loss_sum += a.loss();
+            }
+        }
+
+        assert_eq!(a.num_delivered() + a.num_lost() + a.num_in_flight(), 70_000);
+        assert_eq!(notifications.len(), a.num_delivered() + a.num_lost());
+        assert!(received_by_b.len() <= a.num_in_flight());
+
+        let rtt = a.rtt().smoothed_rtt().unwrap().to_secs();
+        assert!(rtt > 0.09 && rtt < 0.13, "{}", rtt);
+        assert!((loss_sum / 60_000.0 - 0.2).abs() < 0.01, "{}", loss_sum);
+        assert!((a.num_lost() as f64 / 70_000.0 - 0.2).abs() < 0.01);
+
+        let mut metrics = Metrics::new(LocalDt::from_secs(10.0), clock.clone());
+        a.record_metrics("a", &mut metrics);
+        assert!(metrics.get_gauge("a_rtt_var").is_some());
+        assert!(metrics.get_gauge("a_loss").is_some());
+    }
+
+    #[test]
+    fn test_playback_delay() {
+        let send_dt = GameDt::from_millis(50.0);
+        let estimate = |loss| LinkEstimate {
+            rtt: Some(LocalDt::from_millis(100.0)),
+            rtt_var: LocalDt::from_millis(10.0),
+            loss,
+        };
+        let delay = |loss| estimate(loss).playback_delay(send_dt).unwrap().to_secs();
+
+        let no_samples = LinkEstimate {
+            rtt: None,
+            ..estimate(0.0)
+        };
+        assert_eq!(no_samples.playback_delay(send_dt), None);
+
+        // At 20% loss, runs of two losses need to be covered.
+        assert!((delay(0.0) - 0.12).abs() < 1e-9);
+        assert!((delay(0.05) - 0.17).abs() < 1e-9);
+        assert!((delay(0.2) - 0.22).abs() < 1e-9);
+        assert!(delay(1.0) < 30.0);
+    }
+}
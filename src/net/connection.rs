@@ -0,0 +1,702 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    transport::{ClientTransport, ServerTransport},
+    LocalClock, LocalDt, LocalTime, PlayerId,
+};
+
+/// Disconnect packets are not acknowledged, so they are sent multiple times
+/// to make it likely that the peer receives one of them.
+const NUM_DISCONNECT_PACKETS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionParams {
+    /// Clients whose version differs from the server's are denied.
+    pub protocol_version: u32,
+
+    /// The server denies further clients once this many are connected or
+    /// connecting.
+    pub max_clients: usize,
+
+    /// How often a connecting client resends its handshake packets.
+    pub handshake_interval: LocalDt,
+
+    /// A keepalive is sent if nothing else has been sent for this long.
+    pub keepalive_interval: LocalDt,
+
+    /// The connection is dropped if nothing has been received for this long.
+    /// This also bounds the duration of the handshake.
+    pub timeout: LocalDt,
+}
+
+impl Default for ConnectionParams {
+    fn default() -> Self {
+        Self {
+            protocol_version: 0,
+            max_clients: 64,
+            handshake_interval: LocalDt::from_millis(100.0),
+            keepalive_interval: LocalDt::from_millis(250.0),
+            timeout: LocalDt::from_secs(5.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DenyReason {
+    VersionMismatch { server_version: u32 },
+    ServerFull,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// Nothing was received from the peer for too long.
+    Timeout,
+
+    /// The peer has sent a disconnect packet.
+    Remote,
+
+    /// `disconnect` was called on this side.
+    Local,
+
+    /// The server has denied the connection request.
+    Denied(DenyReason),
+}
+
+/// A packet exchanged between a [`ConnectionClient`] and a
+/// [`ConnectionServer`].
+///
+/// The handshake consists of a request by the client, a challenge by the
+/// server and the client's response to the challenge, which the server
+/// accepts. The challenge ensures that the client can receive packets at the
+/// address that it claims to send from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionPacket<T> {
+    Request {
+        protocol_version: u32,
+        client_salt: u64,
+    },
+    Challenge {
+        client_salt: u64,
+        server_salt: u64,
+    },
+    Response {
+        salt: u64,
+    },
+    Accept,
+    Deny(DenyReason),
+    KeepAlive,
+    Payload(T),
+    Disconnect,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerEvent<C> {
+    Connected(PlayerId),
+    Disconnected(PlayerId, DisconnectReason),
+    Message(LocalTime, PlayerId, C),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent<S> {
+    Connected,
+    Disconnected(DisconnectReason),
+    Message(LocalTime, S),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientState {
+    /// Waiting for the server's challenge.
+    Requesting,
+
+    /// Waiting for the server to accept the response to its challenge.
+    Responding,
+
+    Connected,
+    Disconnected(DisconnectReason),
+}
+
+/// The client side of a connection.
+///
+/// Like [`ReliableStream`](super::ReliableStream), the connection does not
+/// own its transport. Instead, [`update`](ConnectionClient::update) is
+/// called once per frame with the transport, which receives and sends all
+/// pending packets and checks for timeouts.
+#[derive(Debug, Clone)]
+pub struct ConnectionClient<S, C> {
+    params: ConnectionParams,
+    clock: LocalClock,
+    state: ClientState,
+
+    client_salt: u64,
+    server_salt: u64,
+
+    start_time: LocalTime,
+    last_receive_time: LocalTime,
+    last_send_time: Option<LocalTime>,
+
+    outgoing: Vec<ConnectionPacket<C>>,
+    events: Vec<ClientEvent<S>>,
+}
+
+impl<S, C> ConnectionClient<S, C> {
+    /// Start connecting to the server.
+    pub fn new(params: ConnectionParams, clock: LocalClock) -> Self {
+        let now = clock.local_time();
+
+        Self {
+            params,
+            clock,
+            state: ClientState::Requesting,
+            client_salt: rand::random(),
+            server_salt: 0,
+            start_time: now,
+            last_receive_time: now,
+            last_send_time: None,
+            outgoing: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn params(&self) -> &ConnectionParams {
+        &self.params
+    }
+
+    pub fn state(&self) -> ClientState {
+        self.state
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state == ClientState::Connected
+    }
+
+    /// Queue a message to be sent with the next update. Messages are
+    /// discarded while the client is not connected.
+    pub fn send(&mut self, message: C) {
+        if self.is_connected() {
+            self.outgoing.push(ConnectionPacket::Payload(message));
+        }
+    }
+
+    /// Disconnect gracefully, letting the server know with the next update.
+    pub fn disconnect(&mut self) {
+        if let ClientState::Disconnected(_) = self.state {
+            return;
+        }
+
+        self.outgoing.clear();
+        for _ in 0..NUM_DISCONNECT_PACKETS {
+            self.outgoing.push(ConnectionPacket::Disconnect);
+        }
+        self.set_disconnected(DisconnectReason::Local);
+    }
+
+    /// Receive and handle packets from the server, check for timeouts and
+    /// send pending packets. Returns the events that have happened since the
+    /// last update.
+    pub fn update<T>(&mut self, transport: &mut T) -> Vec<ClientEvent<S>>
+    where
+        T: ClientTransport<ConnectionPacket<S>, ConnectionPacket<C>>,
+    {
+        for (receive_time, packet) in transport.receive() {
+            self.handle_packet(receive_time, packet);
+        }
+
+        let now = self.clock.local_time();
+
+        match self.state {
+            ClientState::Requesting | ClientState::Responding => {
+                if now >= self.start_time + self.params.timeout {
+                    self.set_disconnected(DisconnectReason::Timeout);
+                } else if self
+                    .last_send_time
+                    .is_none_or(|time| now >= time + self.params.handshake_interval)
+                {
+                    let packet = self.handshake_packet();
+                    self.outgoing.push(packet);
+                }
+            }
+            ClientState::Connected => {
+                if now >= self.last_receive_time + self.params.timeout {
+                    self.set_disconnected(DisconnectReason::Timeout);
+                } else if self.outgoing.is_empty()
+                    && self
+                        .last_send_time
+                        .is_none_or(|time| now >= time + self.params.keepalive_interval)
+                {
+                    self.outgoing.push(ConnectionPacket::KeepAlive);
+                }
+            }
+            ClientState::Disconnected(_) => (),
+        }
+
+        if !self.outgoing.is_empty() {
+            self.last_send_time = Some(now);
+        }
+        for packet in self.outgoing.drain(..) {
+            transport.send(packet);
+        }
+
+        std::mem::take(&mut self.events)
+    }
+
+    fn handshake_packet(&self) -> ConnectionPacket<C> {
+        match self.state {
+            ClientState::Requesting => ConnectionPacket::Request {
+                protocol_version: self.params.protocol_version,
+                client_salt: self.client_salt,
+            },
+            _ => ConnectionPacket::Response {
+                salt: self.client_salt ^ self.server_salt,
+            },
+        }
+    }
+
+    fn handle_packet(&mut self, receive_time: LocalTime, packet: ConnectionPacket<S>) {
+        match (self.state, packet) {
+            (ClientState::Disconnected(_), _) => return,
+            (
+                ClientState::Requesting,
+                ConnectionPacket::Challenge {
+                    client_salt,
+                    server_salt,
+                },
+            ) if client_salt == self.client_salt => {
+                self.state = ClientState::Responding;
+                self.server_salt = server_salt;
+
+                // Respond immediately instead of waiting for the next resend.
+                let packet = self.handshake_packet();
+                self.outgoing.push(packet);
+            }
+            (ClientState::Requesting, ConnectionPacket::Deny(reason))
+            | (ClientState::Responding, ConnectionPacket::Deny(reason)) => {
+                self.set_disconnected(DisconnectReason::Denied(reason));
+            }
+            (ClientState::Responding, ConnectionPacket::Accept) => {
+                self.set_connected();
+            }
+            // If the accept packet is lost, the first packet after it
+            // confirms the connection just as well.
+            (ClientState::Responding, ConnectionPacket::KeepAlive) => {
+                self.set_connected();
+            }
+            (ClientState::Responding, ConnectionPacket::Payload(message)) => {
+                self.set_connected();
+                self.events
+                    .push(ClientEvent::Message(receive_time, message));
+            }
+            (ClientState::Connected, ConnectionPacket::Payload(message)) => {
+                self.events
+                    .push(ClientEvent::Message(receive_time, message));
+            }
+            (ClientState::Connected, ConnectionPacket::KeepAlive)
+            | (ClientState::Connected, ConnectionPacket::Accept) => (),
+            (ClientState::Responding, ConnectionPacket::Disconnect)
+            | (ClientState::Connected, ConnectionPacket::Disconnect) => {
+                self.set_disconnected(DisconnectReason::Remote);
+                return;
+            }
+            // Duplicates and packets of an earlier stage of the handshake.
+            _ => return,
+        }
+
+        self.last_receive_time = self.last_receive_time.max(receive_time);
+    }
+
+    fn set_connected(&mut self) {
+        self.state = ClientState::Connected;
+        self.events.push(ClientEvent::Connected);
+    }
+
+    fn set_disconnected(&mut self, reason: DisconnectReason) {
+        self.state = ClientState::Disconnected(reason);
+        self.events.push(ClientEvent::Disconnected(reason));
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Peer {
+    client_salt: u64,
+    server_salt: u64,
+    is_connected: bool,
+    last_receive_time: LocalTime,
+    last_send_time: LocalTime,
+}
+
+/// The server side of connections to any number of clients.
+///
+/// Clients are identified by the `PlayerId` that the transport assigns to
+/// them, e.g. one per address in the case of
+/// [`UdpServerTransport`](crate::transport::UdpServerTransport).
+#[derive(Debug, Clone)]
+pub struct ConnectionServer<S, C> {
+    params: ConnectionParams,
+    clock: LocalClock,
+    peers: BTreeMap<PlayerId, Peer>,
+
+    outgoing: Vec<(PlayerId, ConnectionPacket<S>)>,
+    events: Vec<ServerEvent<C>>,
+}
+
+impl<S, C> ConnectionServer<S, C> {
+    pub fn new(params: ConnectionParams, clock: LocalClock) -> Self {
+        Self {
+            params,
+            clock,
+            peers: BTreeMap::new(),
+            outgoing: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn params(&self) -> &ConnectionParams {
+        &self.params
+    }
+
+    /// Players that have completed the handshake.
+    pub fn players(&self) -> impl Iterator<Item = PlayerId> + '_ {
+        self.peers
+            .iter()
+            .filter(|(_, peer)| peer.is_connected)
+            .map(|(player, _)| *player)
+    }
+
+    pub fn is_connected(&self, player: PlayerId) -> bool {
+        self.peers
+            .get(&player)
+            .is_some_and(|peer| peer.is_connected)
+    }
+
+    /// Queue a message to be sent with the next update. Messages to players
+    /// that are not connected are discarded.
+    pub fn send(&mut self, player: PlayerId, message: S) {
+        if self.is_connected(player) {
+            self.outgoing
+                .push((player, ConnectionPacket::Payload(message)));
+        }
+    }
+
+    /// Disconnect a player gracefully, letting the client know with the next
+    /// update.
+    pub fn disconnect(&mut self, player: PlayerId) {
+        if !self.is_connected(player) {
+            return;
+        }
+
+        self.outgoing.retain(|(receiver, _)| *receiver != player);
+        for _ in 0..NUM_DISCONNECT_PACKETS {
+            self.outgoing.push((player, ConnectionPacket::Disconnect));
+        }
+        self.remove_peer(player, DisconnectReason::Local);
+    }
+
+    /// Receive and handle packets from clients, check for timeouts and send
+    /// pending packets. Returns the events that have happened since the last
+    /// update.
+    pub fn update<T>(&mut self, transport: &mut T) -> Vec<ServerEvent<C>>
+    where
+        T: ServerTransport<ConnectionPacket<S>, ConnectionPacket<C>>,
+    {
+        for (receive_time, player, packet) in transport.receive() {
+            self.handle_packet(receive_time, player, packet);
+        }
+
+        let now = self.clock.local_time();
+
+        let timed_out: Vec<PlayerId> = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| now >= peer.last_receive_time + self.params.timeout)
+            .map(|(player, _)| *player)
+            .collect();
+        for player in timed_out {
+            self.remove_peer(player, DisconnectReason::Timeout);
+        }
+
+        for (player, peer) in self.peers.iter() {
+            let has_outgoing = self.outgoing.iter().any(|(receiver, _)| receiver == player);
+
+            if peer.is_connected
+                && !has_outgoing
+                && now >= peer.last_send_time + self.params.keepalive_interval
+            {
+                self.outgoing.push((*player, ConnectionPacket::KeepAlive));
+            }
+        }
+
+        for (player, packet) in self.outgoing.drain(..) {
+            if let Some(peer) = self.peers.get_mut(&player) {
+                peer.last_send_time = now;
+            }
+            transport.send(player, packet);
+        }
+
+        std::mem::take(&mut self.events)
+    }
+
+    fn handle_packet(
+        &mut self,
+        receive_time: LocalTime,
+        player: PlayerId,
+        packet: ConnectionPacket<C>,
+    ) {
+        match packet {
+            ConnectionPacket::Request {
+                protocol_version,
+                client_salt,
+            } => {
+                if protocol_version != self.params.protocol_version {
+                    let reason = DenyReason::VersionMismatch {
+                        server_version: self.params.protocol_version,
+                    };
+                    self.outgoing.push((player, ConnectionPacket::Deny(reason)));
+                    return;
+                }
+
+                let is_new = self
+                    .peers
+                    .get(&player)
+                    .is_none_or(|peer| !peer.is_connected && peer.client_salt != client_salt);
+                if is_new {
+                    if self.peers.len() >= self.params.max_clients
+                        && !self.peers.contains_key(&player)
+                    {
+                        self.outgoing
+                            .push((player, ConnectionPacket::Deny(DenyReason::ServerFull)));
+                        return;
+                    }
+
+                    self.peers.insert(
+                        player,
+                        Peer {
+                            client_salt,
+                            server_salt: rand::random(),
+                            is_connected: false,
+                            last_receive_time: receive_time,
+                            last_send_time: receive_time,
+                        },
+                    );
+                }
+
+                // Resend the challenge for repeated requests, since it may
+                // have been lost.
+                let peer = self.peers.get_mut(&player).unwrap();
+                if !peer.is_connected {
+                    peer.last_receive_time = peer.last_receive_time.max(receive_time);
+
+                    let challenge = ConnectionPacket::Challenge {
+                        client_salt: peer.client_salt,
+                        server_salt: peer.server_salt,
+                    };
+                    self.outgoing.push((player, challenge));
+                }
+            }
+            ConnectionPacket::Response { salt } => {
+                let peer = match self.peers.get_mut(&player) {
+                    Some(peer) if salt == peer.client_salt ^ peer.server_salt => peer,
+                    _ => return,
+                };
+                peer.last_receive_time = peer.last_receive_time.max(receive_time);
+
+                if !peer.is_connected {
+                    peer.is_connected = true;
+                    self.events.push(ServerEvent::Connected(player));
+                }
+
+                // Also accept repeated responses, since our accept may have
+                // been lost.
+                self.outgoing.push((player, ConnectionPacket::Accept));
+            }
+            ConnectionPacket::KeepAlive | ConnectionPacket::Payload(_) => {
+                let peer = match self.peers.get_mut(&player) {
+                    Some(peer) if peer.is_connected => peer,
+                    _ => return,
+                };
+                peer.last_receive_time = peer.last_receive_time.max(receive_time);
+
+                if let ConnectionPacket::Payload(message) = packet {
+                    self.events
+                        .push(ServerEvent::Message(receive_time, player, message));
+                }
+            }
+            ConnectionPacket::Disconnect => {
+                if self.peers.contains_key(&player) {
+                    self.remove_peer(player, DisconnectReason::Remote);
+                }
+            }
+            // Packets that only the server sends.
+            ConnectionPacket::Challenge { .. }
+            | ConnectionPacket::Accept
+            | ConnectionPacket::Deny(_) => (),
+        }
+    }
+
+    /// Remove a peer, emitting an event if it has been connected.
+    fn remove_peer(&mut self, player: PlayerId, reason: DisconnectReason) {
+        if let Some(peer) = self.peers.remove(&player) {
+            if peer.is_connected {
+                self.events.push(ServerEvent::Disconnected(player, reason));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ClientEvent, ClientState, ConnectionClient, ConnectionPacket, ConnectionParams,
+        ConnectionServer, DenyReason, DisconnectReason, ServerEvent,
+    };
+    use crate::{
+        mock::{MockChannelParams, MockNet, MockSocketParams},
+        LocalClock, LocalDt, PlayerId,
+    };
+
+    type Net = MockNet<ConnectionPacket<u32>, ConnectionPacket<u32>>;
+    type Client = ConnectionClient<u32, u32>;
+    type Server = ConnectionServer<u32, u32>;
+
+    /// Run for the given duration in frames of 10ms, collecting all events.
+    fn run(
+        duration: LocalDt,
+        clock: &mut LocalClock,
+        net: &mut Net,
+        server: &mut Server,
+        clients: &mut [(PlayerId, &mut Client)],
+    ) -> (Vec<ServerEvent<u32>>, Vec<Vec<ClientEvent<u32>>>) {
+        let mut server_events = Vec::new();
+        let mut client_events = vec![Vec::new(); clients.len()];
+
+        let end_time = clock.local_time() + duration;
+        while clock.local_time() < end_time {
+            clock.advance(LocalDt::from_millis(10.0));
+
+            server_events.extend(server.update(net));
+            for ((player, client), events) in clients.iter_mut().zip(client_events.iter_mut()) {
+                events.extend(client.update(&mut net.client(*player)));
+            }
+        }
+
+        (server_events, client_events)
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut clock = LocalClock::new();
+        let mut net = Net::new(&[PlayerId(0), PlayerId(1)], clock.clone());
+        net.set_seed(1);
+        let lossy = MockChannelParams {
+            latency_mean: LocalDt::from_millis(50.0),
+            loss: 0.3,
+            ..MockChannelParams::perfect()
+        };
+        net.set_params(
+            PlayerId(0),
+            MockSocketParams {
+                server_out: lossy.clone(),
+                client_out: lossy,
+            },
+        );
+
+        let params = ConnectionParams {
+            protocol_version: 1,
+            timeout: LocalDt::from_secs(3.0),
+            ..ConnectionParams::default()
+        };
+        let mut server = Server::new(params.clone(), clock.clone());
+        let mut client = Client::new(params.clone(), clock.clone());
+        let mut outdated = Client::new(
+            ConnectionParams {
+                protocol_version: 0,
+                ..params.clone()
+            },
+            clock.clone(),
+        );
+
+        // The handshake completes despite the loss, and the outdated client is
+        // denied.
+        let (server_events, client_events) = run(
+            LocalDt::from_secs(2.0),
+            &mut clock,
+            &mut net,
+            &mut server,
+            &mut [(PlayerId(0), &mut client), (PlayerId(1), &mut outdated)],
+        );
+        assert_eq!(server_events, vec![ServerEvent::Connected(PlayerId(0))]);
+        assert_eq!(client_events[0], vec![ClientEvent::Connected]);
+        let denied = DisconnectReason::Denied(DenyReason::VersionMismatch { server_version: 1 });
+        assert_eq!(client_events[1], vec![ClientEvent::Disconnected(denied)]);
+        assert_eq!(outdated.state(), ClientState::Disconnected(denied));
+
+        // Keepalives prevent timeouts while no messages are sent.
+        server.send(PlayerId(0), 42);
+        let (server_events, client_events) = run(
+            LocalDt::from_secs(5.0),
+            &mut clock,
+            &mut net,
+            &mut server,
+            &mut [(PlayerId(0), &mut client)],
+        );
+        assert!(server_events.is_empty());
+        assert!(client_events[0]
+            .iter()
+            .all(|event| matches!(event, ClientEvent::Message(_, 42))));
+        assert!(!client_events[0].is_empty());
+        assert!(client.is_connected());
+        assert_eq!(server.players().collect::<Vec<_>>(), vec![PlayerId(0)]);
+
+        // Graceful disconnect. Since disconnect packets are unreliable, use a
+        // perfect channel from here on to keep the test deterministic.
+        net.set_params(PlayerId(0), MockSocketParams::perfect());
+        client.disconnect();
+        let (server_events, client_events) = run(
+            LocalDt::from_secs(1.0),
+            &mut clock,
+            &mut net,
+            &mut server,
+            &mut [(PlayerId(0), &mut client)],
+        );
+        assert_eq!(
+            server_events,
+            vec![ServerEvent::Disconnected(
+                PlayerId(0),
+                DisconnectReason::Remote
+            )]
+        );
+        assert_eq!(
+            client_events[0],
+            vec![ClientEvent::Disconnected(DisconnectReason::Local)]
+        );
+
+        // Reconnect, and then lose the connection entirely.
+        let mut client = Client::new(params, clock.clone());
+        let (server_events, _) = run(
+            LocalDt::from_secs(1.0),
+            &mut clock,
+            &mut net,
+            &mut server,
+            &mut [(PlayerId(0), &mut client)],
+        );
+        assert_eq!(server_events, vec![ServerEvent::Connected(PlayerId(0))]);
+
+        net.disconnect(PlayerId(0));
+        let (server_events, client_events) = run(
+            LocalDt::from_secs(4.0),
+            &mut clock,
+            &mut net,
+            &mut server,
+            &mut [(PlayerId(0), &mut client)],
+        );
+        assert_eq!(
+            server_events,
+            vec![ServerEvent::Disconnected(
+                PlayerId(0),
+                DisconnectReason::Timeout
+            )]
+        );
+        assert_eq!(
+            client_events[0],
+            vec![ClientEvent::Disconnected(DisconnectReason::Timeout)]
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use std::{collections::BTreeMap, convert::TryInto};
+
+use crate::{LocalClock, LocalDt, LocalTime, Metrics};
+
+/// Size of the header that [`Fragment::encode`] prepends to the payload.
+pub const FRAGMENT_HEADER_SIZE: usize = 8;
+
+/// Default maximum number of fragments per message that a [`Reassembler`]
+/// accepts.
+pub const DEFAULT_MAX_FRAGMENTS: usize = 256;
+
+/// Default maximum number of partial messages that a [`Reassembler`] keeps.
+pub const DEFAULT_MAX_PARTIAL: usize = 64;
+
+/// A part of a message that has been split by a [`Fragmenter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    pub message_id: u32,
+    pub index: u16,
+    pub count: u16,
+    pub payload: Vec<u8>,
+}
+
+impl Fragment {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FRAGMENT_HEADER_SIZE + self.payload.len());
+        bytes.extend_from_slice(&self.message_id.to_le_bytes());
+        bytes.extend_from_slice(&self.index.to_le_bytes());
+        bytes.extend_from_slice(&self.count.to_le_bytes());
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+
+    /// Returns `None` if the bytes do not contain a valid fragment header.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < FRAGMENT_HEADER_SIZE {
+            return None;
+        }
+
+        let fragment = Fragment {
+            message_id: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+            index: u16::from_le_bytes(bytes[4..6].try_into().unwrap()),
+            count: u16::from_le_bytes(bytes[6..8].try_into().unwrap()),
+            payload: bytes[FRAGMENT_HEADER_SIZE..].to_vec(),
+        };
+
+        if fragment.index < fragment.count {
+            Some(fragment)
+        } else {
+            None
+        }
+    }
+
+    /// The size of the fragment when encoded.
+    pub fn size(&self) -> usize {
+        FRAGMENT_HEADER_SIZE + self.payload.len()
+    }
+}
+
+/// Splits messages into fragments whose payload is at most
+/// `max_payload_size` bytes.
+#[derive(Debug, Clone)]
+pub struct Fragmenter {
+    max_payload_size: usize,
+    next_message_id: u32,
+}
+
+impl Fragmenter {
+    pub fn new(max_payload_size: usize) -> Self {
+        assert!(max_payload_size > 0, "Fragments need a non-empty payload");
+
+        Self {
+            max_payload_size,
+            next_message_id: 0,
+        }
+    }
+
+    /// Create a fragmenter whose encoded fragments fit into the given MTU.
+    pub fn for_mtu(mtu: usize) -> Self {
+        assert!(
+            mtu > FRAGMENT_HEADER_SIZE,
+            "MTU {} does not leave room for a fragment payload",
+            mtu
+        );
+
+        Self::new(mtu - FRAGMENT_HEADER_SIZE)
+    }
+
+    pub fn fragment(&mut self, message: &[u8]) -> Vec<Fragment> {
+        let message_id = self.next_message_id;
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+
+        // Empty messages still need one fragment to arrive.
+        let chunks: Vec<_> = if message.is_empty() {
+            vec![message]
+        } else {
+            message.chunks(self.max_payload_size).collect()
+        };
+        assert!(
+            chunks.len() <= u16::MAX as usize,
+            "Message has too many fragments"
+        );
+
+        let count = chunks.len() as u16;
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(index, chunk)| Fragment {
+                message_id,
+                index: index as u16,
+                count,
+                payload: chunk.to_vec(),
+            })
+            .collect()
+    }
+}
+
+/// Counters of a [`Reassembler`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FragmentStats {
+    pub num_fragments_received: usize,
+
+    /// Fragments that were ignored because their message has too many
+    /// fragments.
+    pub num_fragments_rejected: usize,
+
+    /// Fragments that were missing from messages that timed out or were
+    /// evicted.
+    pub num_fragments_missing: usize,
+
+    pub num_messages_completed: usize,
+    pub num_messages_expired: usize,
+
+    /// Partial messages that were dropped to make room for newer ones.
+    pub num_messages_evicted: usize,
+}
+
+impl FragmentStats {
+    /// Fraction of fragments of finished messages that never arrived.
+    pub fn fragment_loss(&self) -> Option<f64> {
+        let num_total = self.num_fragments_received + self.num_fragments_missing;
+
+        if num_total > 0 {
+            Some(self.num_fragments_missing as f64 / num_total as f64)
+        } else {
+            None
+        }
+    }
+
+    /// Fraction of finished messages that were dropped because a fragment
+    /// was missing.
+    pub fn message_loss(&self) -> Option<f64> {
+        let num_dropped = self.num_messages_expired + self.num_messages_evicted;
+        let num_total = self.num_messages_completed + num_dropped;
+
+        if num_total > 0 {
+            Some(num_dropped as f64 / num_total as f64)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Partial {
+    first_receive_time: LocalTime,
+    fragments: Vec<Option<Vec<u8>>>,
+    num_received: usize,
+}
+
+/// Reassembles messages from fragments that can arrive in any order.
+///
+/// If not all fragments of a message have arrived within `timeout` of its
+/// first fragment, the whole message is dropped.
+///
+/// Since fragments come from the network, the memory that they can take up
+/// is bounded: Messages with more than `max_fragments` fragments are ignored,
+/// and if there are more than `max_partial` incomplete messages, the oldest
+/// one is evicted.
+#[derive(Debug, Clone)]
+pub struct Reassembler {
+    clock: LocalClock,
+    timeout: LocalDt,
+    max_fragments: usize,
+    max_partial: usize,
+    partials: BTreeMap<u32, Partial>,
+    stats: FragmentStats,
+}
+
+impl Reassembler {
+    pub fn new(timeout: LocalDt, clock: LocalClock) -> Self {
+        Self::with_limits(timeout, DEFAULT_MAX_FRAGMENTS, DEFAULT_MAX_PARTIAL, clock)
+    }
+
+    pub fn with_limits(
+        timeout: LocalDt,
+        max_fragments: usize,
+        max_partial: usize,
+        clock: LocalClock,
+    ) -> Self {
+        assert!(max_partial > 0, "Reassembler needs room for one message");
+
+        Self {
+            clock,
+            timeout,
+            max_fragments,
+            max_partial,
+            partials: BTreeMap::new(),
+            stats: FragmentStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &FragmentStats {
+        &self.stats
+    }
+
+    pub fn num_partial(&self) -> usize {
+        self.partials.len()
+    }
+
+    /// Add a fragment, returning the message if it is now complete.
+    pub fn receive(&mut self, fragment: Fragment) -> Option<Vec<u8>> {
+        self.expire();
+
+        if fragment.count as usize > self.max_fragments {
+            self.stats.num_fragments_rejected += 1;
+            return None;
+        }
+
+        if !self.partials.contains_key(&fragment.message_id)
+            && self.partials.len() >= self.max_partial
+        {
+            self.evict_oldest();
+        }
+
+        let now = self.clock.local_time();
+        let partial = self
+            .partials
+            .entry(fragment.message_id)
+            .or_insert_with(|| Partial {
+                first_receive_time: now,
+                fragments: vec![None; fragment.count as usize],
+                num_received: 0,
+            });
+
+        let slot = match partial.fragments.get_mut(fragment.index as usize) {
+            Some(slot) if slot.is_none() => slot,
+            // Ignore duplicates and fragments that disagree on the count.
+            _ => return None,
+        };
+
+        *slot = Some(fragment.payload);
+        partial.num_received += 1;
+        self.stats.num_fragments_received += 1;
+
+        if partial.num_received < partial.fragments.len() {
+            return None;
+        }
+
+        let partial = self.partials.remove(&fragment.message_id).unwrap();
+        self.stats.num_messages_completed += 1;
+
+        Some(partial.fragments.into_iter().flatten().flatten().collect())
+    }
+
+    /// Drop all messages whose fragments did not arrive in time. This is
+    /// also done whenever a fragment is received.
+    pub fn expire(&mut self) {
+        let now = self.clock.local_time();
+        let timeout = self.timeout;
+        let stats = &mut self.stats;
+
+        self.partials.retain(|_, partial| {
+            let expired = now - partial.first_receive_time > timeout;
+
+            if expired {
+                stats.num_messages_expired += 1;
+                stats.num_fragments_missing += partial.fragments.len() - partial.num_received;
+            }
+
+            !expired
+        });
+    }
+
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .partials
+            .iter()
+            .min_by(|(_, a), (_, b)| {
+                a.first_receive_time
+                    .partial_cmp(&b.first_receive_time)
+                    .unwrap()
+            })
+            .map(|(message_id, _)| *message_id);
+
+        if let Some(partial) = oldest.and_then(|message_id| self.partials.remove(&message_id)) {
+            self.stats.num_messages_evicted += 1;
+            self.stats.num_fragments_missing += partial.fragments.len() - partial.num_received;
+        }
+    }
+
+    pub fn record_metrics(&self, prefix: &str, metrics: &mut Metrics) {
+        if let Some(loss) = self.stats.fragment_loss() {
+            metrics.record_gauge(&format!("{}_fragment_loss", prefix), loss);
+        }
+        if let Some(loss) = self.stats.message_loss() {
+            metrics.record_gauge(&format!("{}_fragmented_message_loss", prefix), loss);
+        }
+        metrics.record_gauge(
+            &format!("{}_num_partial_messages", prefix),
+            self.num_partial() as f64,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fragment, Fragmenter, Reassembler};
+    use crate::{
+        mock::{MockChannel, MockChannelParams, MockLinkCapacity},
+        LocalClock, LocalDt,
+    };
+
+    #[test]
+    fn test_lossy_link() {
+        let mut clock = LocalClock::new();
+        let params = MockChannelParams {
+            latency_mean: LocalDt::from_millis(50.0),
+            loss: 0.1,
+            ..MockChannelParams::perfect()
+        };
+
+        // 1 MB/s, so that a snapshot of 5000 bytes takes 5ms to transmit.
+        let mut link = MockLinkCapacity::new(1_000_000.0, Some(1400));
+        let mut channel = MockChannel::new(clock.clone());
+        let mut fragmenter = Fragmenter::for_mtu(1400);
+        let mut reassembler = Reassembler::new(LocalDt::from_millis(500.0), clock.clone());
+
+        let snapshot: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+        assert!(link.transmit(clock.local_time(), snapshot.len()).is_none());
+
+        let mut num_received = 0;
+        for frame in 0..1100 {
+            if frame < 1000 {
+                for fragment in fragmenter.fragment(&snapshot) {
+                    let bytes = fragment.encode();
+                    let residual = link
+                        .transmit(clock.local_time(), bytes.len())
+                        .zip(channel.sample_residual(&params))
+                        .map(|(transmit_delay, latency)| transmit_delay + latency);
+                    channel.send_with_residual(residual, bytes);
+                }
+            }
+
+            clock.advance(LocalDt::from_millis(50.0));
+
+            while let Some((_, bytes)) = channel.receive() {
+                let fragment = Fragment::decode(&bytes).unwrap();
+                if let Some(message) = reassembler.receive(fragment) {
+                    assert_eq!(message, snapshot);
+                    num_received += 1;
+                }
+            }
+        }
+
+        clock.advance(LocalDt::from_secs(1.0));
+        reassembler.expire();
+
+        // Each snapshot has 4 fragments, so about 0.9^4 of them arrive. The
+        // reassembler never sees snapshots that lose all of their fragments.
+        let stats = reassembler.stats();
+        assert_eq!(stats.num_messages_completed, num_received);
+        assert!(stats.num_messages_completed + stats.num_messages_expired > 995);
+        assert!((stats.fragment_loss().unwrap() - 0.1).abs() < 0.03);
+        assert!((stats.message_loss().unwrap() - (1.0 - 0.9f64.powi(4))).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut clock = LocalClock::new();
+        let mut fragmenter = Fragmenter::new(1);
+        let mut reassembler =
+            Reassembler::with_limits(LocalDt::from_secs(1.0), 4, 2, clock.clone());
+
+        // Messages with too many fragments are ignored.
+        for fragment in fragmenter.fragment(&[0; 5]) {
+            assert_eq!(reassembler.receive(fragment), None);
+        }
+        assert_eq!(reassembler.stats().num_fragments_rejected, 5);
+        assert_eq!(reassembler.num_partial(), 0);
+
+        // Only the two newest partial messages are kept.
+        let messages: Vec<_> = (0..3).map(|i| fragmenter.fragment(&[i; 2])).collect();
+        for fragments in messages.iter() {
+            reassembler.receive(fragments[0].clone());
+            clock.advance(LocalDt::from_millis(10.0));
+        }
+        assert_eq!(reassembler.num_partial(), 2);
+        assert_eq!(reassembler.stats().num_messages_evicted, 1);
+
+        assert_eq!(
+            reassembler.receive(messages[2][1].clone()),
+            Some(vec![2; 2])
+        );
+        assert_eq!(reassembler.receive(messages[0][1].clone()), None);
+        assert_eq!(reassembler.stats().num_messages_evicted, 1);
+
+        // Stale messages are dropped.
+        clock.advance(LocalDt::from_secs(2.0));
+        reassembler.expire();
+        assert_eq!(reassembler.num_partial(), 0);
+        assert_eq!(reassembler.stats().num_messages_expired, 2);
+    }
+}
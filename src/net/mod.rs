@@ -0,0 +1,20 @@
+//! Network protocol building blocks that run on top of any unreliable
+//! [transport](crate::transport).
+
+mod ack;
+mod connection;
+mod fragment;
+mod reliable;
+mod rtt;
+
+pub use ack::{LinkEstimate, PacketAcks, PacketHeader, PacketNotification, ACK_BITS};
+pub use connection::{
+    ClientEvent, ClientState, ConnectionClient, ConnectionPacket, ConnectionParams,
+    ConnectionServer, DenyReason, DisconnectReason, ServerEvent,
+};
+pub use fragment::{
+    Fragment, FragmentStats, Fragmenter, Reassembler, DEFAULT_MAX_FRAGMENTS, DEFAULT_MAX_PARTIAL,
+    FRAGMENT_HEADER_SIZE,
+};
+pub use reliable::{ReliableOrder, ReliablePacket, ReliableStream};
+pub use rtt::RttEstimator;
@@ -0,0 +1,291 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use crate::{LocalClock, LocalTime, Metrics};
+
+use super::RttEstimator;
+
+/// In which order a [`ReliableStream`] delivers messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReliableOrder {
+    /// Messages are delivered in the order in which they were sent. A lost
+    /// message holds back all later messages until it is retransmitted.
+    Ordered,
+
+    /// Messages are delivered as soon as they arrive.
+    Unordered,
+}
+
+/// A packet exchanged between two [`ReliableStream`]s.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReliablePacket<T> {
+    /// Sequence numbers of messages received from the peer.
+    pub acks: Vec<u64>,
+
+    pub messages: Vec<(u64, T)>,
+}
+
+#[derive(Debug, Clone)]
+struct Unacked<T> {
+    message: T,
+    first_send_time: LocalTime,
+    last_send_time: LocalTime,
+    num_sends: u32,
+}
+
+/// One end of a reliable message stream over an unreliable transport.
+///
+/// The stream does not perform any IO itself. Packets created by
+/// [`poll_packet`](ReliableStream::poll_packet) need to be sent to the peer
+/// over some transport, e.g. a [`MockChannel`](crate::mock::MockChannel), and
+/// packets from the peer are passed to
+/// [`receive_packet`](ReliableStream::receive_packet).
+///
+/// Every message has a sequence number and is retransmitted until the peer
+/// acknowledges it. The retransmission timeout is derived from the measured
+/// RTT, and doubles with every retransmission of a message, up to the
+/// maximum timeout of the [`RttEstimator`]. Duplicates are suppressed on the
+/// receiving side.
+#[derive(Debug, Clone)]
+pub struct ReliableStream<T> {
+    clock: LocalClock,
+    order: ReliableOrder,
+    rtt: RttEstimator,
+
+    next_send_seq: u64,
+    unacked: BTreeMap<u64, Unacked<T>>,
+    num_retransmissions: usize,
+
+    // All messages before `next_receive_seq` have been received, as well as
+    // those in `received_ahead`.
+    next_receive_seq: u64,
+    received_ahead: BTreeSet<u64>,
+    held_back: BTreeMap<u64, T>,
+    pending_acks: Vec<u64>,
+    delivered: VecDeque<T>,
+}
+
+impl<T> ReliableStream<T>
+where
+    T: Clone,
+{
+    pub fn new(order: ReliableOrder, clock: LocalClock) -> Self {
+        Self::with_rtt_estimator(order, RttEstimator::default(), clock)
+    }
+
+    pub fn with_rtt_estimator(order: ReliableOrder, rtt: RttEstimator, clock: LocalClock) -> Self {
+        Self {
+            clock,
+            order,
+            rtt,
+            next_send_seq: 0,
+            unacked: BTreeMap::new(),
+            num_retransmissions: 0,
+            next_receive_seq: 0,
+            received_ahead: BTreeSet::new(),
+            held_back: BTreeMap::new(),
+            pending_acks: Vec::new(),
+            delivered: VecDeque::new(),
+        }
+    }
+
+    pub fn order(&self) -> ReliableOrder {
+        self.order
+    }
+
+    pub fn rtt(&self) -> &RttEstimator {
+        &self.rtt
+    }
+
+    /// Number of sent messages that have not been acknowledged yet.
+    pub fn num_unacked(&self) -> usize {
+        self.unacked.len()
+    }
+
+    pub fn num_retransmissions(&self) -> usize {
+        self.num_retransmissions
+    }
+
+    /// Queue a message to be sent with the next packet.
+    pub fn send(&mut self, message: T) {
+        let seq = self.next_send_seq;
+        self.next_send_seq += 1;
+
+        // A `num_sends` of zero marks the message as not having been sent yet.
+        let time = self.clock.local_time();
+        self.unacked.insert(
+            seq,
+            Unacked {
+                message,
+                first_send_time: time,
+                last_send_time: time,
+                num_sends: 0,
+            },
+        );
+    }
+
+    /// Returns a packet containing all pending acks, new messages and
+    /// messages whose retransmission timeout has expired, or `None` if there
+    /// is nothing to send.
+    pub fn poll_packet(&mut self) -> Option<ReliablePacket<T>> {
+        let now = self.clock.local_time();
+        let rto = self.rtt.rto();
+        let mut messages = Vec::new();
+
+        for (seq, unacked) in self.unacked.iter_mut() {
+            let backoff = 2.0f64.powi(unacked.num_sends.max(1) as i32 - 1);
+            let timeout = (rto * backoff).min(self.rtt.max_rto());
+
+            if unacked.num_sends == 0 || now >= unacked.last_send_time + timeout {
+                if unacked.num_sends == 0 {
+                    unacked.first_send_time = now;
+                } else {
+                    self.num_retransmissions += 1;
+                }
+
+                unacked.last_send_time = now;
+                unacked.num_sends += 1;
+                messages.push((*seq, unacked.message.clone()));
+            }
+        }
+
+        let acks = std::mem::take(&mut self.pending_acks);
+
+        if acks.is_empty() && messages.is_empty() {
+            None
+        } else {
+            Some(ReliablePacket { acks, messages })
+        }
+    }
+
+    pub fn receive_packet(&mut self, packet: ReliablePacket<T>) {
+        let now = self.clock.local_time();
+
+        for ack in packet.acks {
+            if let Some(unacked) = self.unacked.remove(&ack) {
+                // Following Karn's algorithm, only messages that were sent
+                // once give an unambiguous RTT sample.
+                if unacked.num_sends == 1 {
+                    self.rtt.record_sample(now - unacked.first_send_time);
+                }
+            }
+        }
+
+        for (seq, message) in packet.messages {
+            // Always ack, since our previous ack may have been lost.
+            self.pending_acks.push(seq);
+
+            let is_duplicate = seq < self.next_receive_seq || !self.received_ahead.insert(seq);
+            if is_duplicate {
+                continue;
+            }
+
+            match self.order {
+                ReliableOrder::Ordered => {
+                    self.held_back.insert(seq, message);
+                }
+                ReliableOrder::Unordered => self.delivered.push_back(message),
+            }
+
+            while self.received_ahead.remove(&self.next_receive_seq) {
+                if let Some(message) = self.held_back.remove(&self.next_receive_seq) {
+                    self.delivered.push_back(message);
+                }
+                self.next_receive_seq += 1;
+            }
+        }
+    }
+
+    /// Returns the messages that have been delivered since the last call.
+    pub fn receive(&mut self) -> Vec<T> {
+        self.delivered.drain(..).collect()
+    }
+
+    pub fn record_metrics(&self, prefix: &str, metrics: &mut Metrics) {
+        if let Some(rtt) = self.rtt.smoothed_rtt() {
+            metrics.record_gauge(&format!("{}_rtt", prefix), rtt.to_secs());
+        }
+        metrics.record_gauge(&format!("{}_rto", prefix), self.rtt.rto().to_secs());
+        metrics.record_gauge(
+            &format!("{}_num_unacked", prefix),
+            self.num_unacked() as f64,
+        );
+        metrics.record_gauge(
+            &format!("{}_num_retransmissions", prefix),
+            self.num_retransmissions as f64,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReliableOrder, ReliableStream};
+    use crate::{
+        mock::{MockChannel, MockChannelParams},
+        net::RttEstimator,
+        LocalClock, LocalDt,
+    };
+
+    fn run_lossy(order: ReliableOrder) -> Vec<u32> {
+        let mut clock = LocalClock::new();
+        let params = MockChannelParams {
+            latency_mean: LocalDt::from_millis(30.0),
+            latency_std_dev: LocalDt::from_millis(10.0),
+            loss: 0.3,
+            loss_correlation: 0.3,
+        };
+
+        let rtt = RttEstimator::new(LocalDt::from_millis(50.0), LocalDt::from_millis(500.0));
+        let mut sender = ReliableStream::with_rtt_estimator(order, rtt.clone(), clock.clone());
+        let mut receiver = ReliableStream::with_rtt_estimator(order, rtt, clock.clone());
+        let mut to_receiver = MockChannel::new(clock.clone());
+        let mut to_sender = MockChannel::new(clock.clone());
+        let mut received = Vec::new();
+
+        for frame in 0..2000 {
+            if frame < 100 {
+                sender.send(frame);
+            }
+
+            if let Some(packet) = sender.poll_packet() {
+                to_receiver.send(&params, packet);
+            }
+            if let Some(packet) = receiver.poll_packet() {
+                to_sender.send(&params, packet);
+            }
+
+            clock.advance(LocalDt::from_millis(10.0));
+
+            while let Some((_, packet)) = to_receiver.receive() {
+                receiver.receive_packet(packet);
+            }
+            while let Some((_, packet)) = to_sender.receive() {
+                sender.receive_packet(packet);
+            }
+            received.extend(receiver.receive());
+        }
+
+        assert_eq!(sender.num_unacked(), 0);
+        assert!(sender.num_retransmissions() > 0);
+        let rtt = sender.rtt().smoothed_rtt().unwrap().to_secs();
+        assert!(rtt > 0.05 && rtt < 0.1);
+
+        received
+    }
+
+    #[test]
+    fn test_ordered() {
+        assert_eq!(
+            run_lossy(ReliableOrder::Ordered),
+            (0..100).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_unordered() {
+        let mut received = run_lossy(ReliableOrder::Unordered);
+        assert_ne!(received, (0..100).collect::<Vec<_>>());
+
+        received.sort_unstable();
+        assert_eq!(received, (0..100).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,77 @@
+use crate::LocalDt;
+
+/// Estimates the round-trip time and the retransmission timeout from RTT
+/// samples, following RFC 6298.
+#[derive(Debug, Clone)]
+pub struct RttEstimator {
+    smoothed_rtt: Option<LocalDt>,
+    rtt_var: LocalDt,
+    latest_rtt: Option<LocalDt>,
+    min_rto: LocalDt,
+    max_rto: LocalDt,
+}
+
+impl Default for RttEstimator {
+    fn default() -> Self {
+        Self::new(LocalDt::from_millis(50.0), LocalDt::from_secs(2.0))
+    }
+}
+
+impl RttEstimator {
+    /// Create an estimator whose retransmission timeout is clamped to the
+    /// given bounds. Games usually want a much smaller lower bound than the
+    /// one second of RFC 6298.
+    pub fn new(min_rto: LocalDt, max_rto: LocalDt) -> Self {
+        Self {
+            smoothed_rtt: None,
+            rtt_var: LocalDt::zero(),
+            latest_rtt: None,
+            min_rto,
+            max_rto,
+        }
+    }
+
+    pub fn record_sample(&mut self, rtt: LocalDt) {
+        self.latest_rtt = Some(rtt);
+
+        match self.smoothed_rtt {
+            Some(smoothed_rtt) => {
+                let deviation = (smoothed_rtt - rtt).max(rtt - smoothed_rtt);
+                self.rtt_var = self.rtt_var * 0.75 + deviation * 0.25;
+                self.smoothed_rtt = Some(smoothed_rtt * 0.875 + rtt * 0.125);
+            }
+            None => {
+                self.smoothed_rtt = Some(rtt);
+                self.rtt_var = rtt * 0.5;
+            }
+        }
+    }
+
+    pub fn smoothed_rtt(&self) -> Option<LocalDt> {
+        self.smoothed_rtt
+    }
+
+    pub fn rtt_var(&self) -> LocalDt {
+        self.rtt_var
+    }
+
+    pub fn latest_rtt(&self) -> Option<LocalDt> {
+        self.latest_rtt
+    }
+
+    pub fn max_rto(&self) -> LocalDt {
+        self.max_rto
+    }
+
+    /// The time after which an unacknowledged message should be sent again.
+    /// Before the first sample, a timeout of one second is used, as in RFC
+    /// 6298.
+    pub fn rto(&self) -> LocalDt {
+        match self.smoothed_rtt {
+            Some(smoothed_rtt) => (smoothed_rtt + self.rtt_var * 4.0)
+                .max(self.min_rto)
+                .min(self.max_rto),
+            None => LocalDt::from_secs(1.0).max(self.min_rto).min(self.max_rto),
+        }
+    }
+}
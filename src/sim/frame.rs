@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+
+use crate::{LocalClock, LocalDt, LocalTime};
+
+use super::Scheduler;
+
+/// Describes when a simulated node runs its frames.
+#[derive(Debug, Clone)]
+pub struct FrameSchedule {
+    pub frame_dt: LocalDt,
+
+    /// Standard deviation of the frame duration, for simulating a variable
+    /// frame rate.
+    pub frame_dt_std_dev: LocalDt,
+
+    /// If set, a hitch occurs with this period, which delays the next frame
+    /// by `hitch_duration`. This can be used to simulate e.g. GC pauses.
+    pub hitch_period: Option<LocalDt>,
+    pub hitch_duration: LocalDt,
+}
+
+impl FrameSchedule {
+    pub fn fixed_hz(hz: f64) -> Self {
+        Self {
+            frame_dt: LocalDt::from_hz(hz),
+            frame_dt_std_dev: LocalDt::zero(),
+            hitch_period: None,
+            hitch_duration: LocalDt::zero(),
+        }
+    }
+
+    pub fn variable_hz(hz: f64, frame_dt_std_dev: LocalDt) -> Self {
+        Self {
+            frame_dt_std_dev,
+            ..Self::fixed_hz(hz)
+        }
+    }
+
+    pub fn with_hitches(self, hitch_period: LocalDt, hitch_duration: LocalDt) -> Self {
+        Self {
+            hitch_period: Some(hitch_period),
+            hitch_duration,
+            ..self
+        }
+    }
+
+    pub fn sample_frame_dt<R: Rng>(&self, rng: &mut R) -> LocalDt {
+        let distribution =
+            Normal::new(self.frame_dt.to_secs(), self.frame_dt_std_dev.to_secs()).unwrap();
+
+        // Keep frames from taking zero or negative time.
+        LocalDt::from_secs(distribution.sample(rng)).max(self.frame_dt * 0.1)
+    }
+}
+
+/// Decides when the frames of a single node happen.
+///
+/// Frames are scheduled in the time of the root clock, but the `dt` of a
+/// frame is measured on the node's own, possibly derived, clock.
+#[derive(Debug, Clone)]
+pub struct FrameTimer {
+    schedule: FrameSchedule,
+    clock: LocalClock,
+    next_frame_time: LocalTime,
+    next_hitch_time: Option<LocalTime>,
+    last_frame_local_time: Option<LocalTime>,
+    rng: StdRng,
+}
+
+impl FrameTimer {
+    pub fn new(schedule: FrameSchedule, clock: LocalClock) -> Self {
+        let next_frame_time = clock.root_time();
+        let next_hitch_time = schedule
+            .hitch_period
+            .map(|hitch_period| next_frame_time + hitch_period);
+
+        Self {
+            schedule,
+            clock,
+            next_frame_time,
+            next_hitch_time,
+            last_frame_local_time: None,
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seed the random number generator that varies frame durations, so that
+    /// runs can be reproduced.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn schedule(&self) -> &FrameSchedule {
+        &self.schedule
+    }
+
+    /// Returns the root time at which the next frame starts.
+    pub fn next_frame_time(&self) -> LocalTime {
+        self.next_frame_time
+    }
+
+    /// Start a frame if one is due at the current root time.
+    ///
+    /// Returns the time that has passed on the node's clock since the last
+    /// frame.
+    pub fn poll(&mut self) -> Option<LocalDt> {
+        let root_time = self.clock.root_time();
+        if root_time < self.next_frame_time {
+            return None;
+        }
+
+        let mut next_frame_time = root_time + self.schedule.sample_frame_dt(&mut self.rng);
+
+        if let Some(next_hitch_time) = self.next_hitch_time {
+            if root_time >= next_hitch_time {
+                next_frame_time += self.schedule.hitch_duration;
+                self.next_hitch_time = self
+                    .schedule
+                    .hitch_period
+                    .map(|hitch_period| next_hitch_time + hitch_period);
+            }
+        }
+
+        self.next_frame_time = next_frame_time;
+
+        let local_time = self.clock.local_time();
+        let dt = self
+            .last_frame_local_time
+            .map_or(LocalDt::zero(), |last_time| local_time - last_time);
+        self.last_frame_local_time = Some(local_time);
+
+        Some(dt)
+    }
+}
+
+/// Runs the frames of multiple nodes, each with their own [`FrameSchedule`].
+///
+/// The root clock is advanced directly to the next frame of any node, so
+/// nodes only get to observe messages at their own frame boundaries.
+#[derive(Debug, Clone)]
+pub struct FrameDriver<K> {
+    scheduler: Scheduler<K>,
+    timers: BTreeMap<K, FrameTimer>,
+    rng: StdRng,
+}
+
+impl<K> FrameDriver<K>
+where
+    K: Ord + Copy,
+{
+    pub fn new(clock: LocalClock) -> Self {
+        Self {
+            scheduler: Scheduler::new(clock),
+            timers: BTreeMap::new(),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    /// Seed the timers of all nodes that are added from now on, so that runs
+    /// can be reproduced.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Add a node whose frame `dt` is measured on `node_clock`, which must be
+    /// derived from the driver's clock (or be the same).
+    pub fn add_node(&mut self, key: K, schedule: FrameSchedule, node_clock: LocalClock) {
+        let mut timer = FrameTimer::new(schedule, node_clock);
+        timer.set_seed(self.rng.gen());
+
+        self.scheduler.schedule(timer.next_frame_time(), key);
+        self.timers.insert(key, timer);
+    }
+
+    pub fn remove_node(&mut self, key: K) {
+        self.timers.remove(&key);
+    }
+
+    pub fn next_frame_time(&self) -> Option<LocalTime> {
+        self.scheduler.next_event_time()
+    }
+
+    /// Start the frames of all nodes that are due at the current time.
+    pub fn poll(&mut self) -> Vec<(K, LocalDt)> {
+        let mut frames = Vec::new();
+
+        while let Some(key) = self.scheduler.pop_due() {
+            // Events of removed nodes are stale and can be ignored.
+            if let Some(timer) = self.timers.get_mut(&key) {
+                if let Some(dt) = timer.poll() {
+                    self.scheduler.schedule(timer.next_frame_time(), key);
+                    frames.push((key, dt));
+                }
+            }
+        }
+
+        frames
+    }
+
+    /// Advance the root clock to the next frame of any node, but not beyond
+    /// `end`, and start the frames of all nodes that are due then.
+    pub fn advance_until(&mut self, end: LocalTime) -> Vec<(K, LocalDt)> {
+        self.scheduler.advance_until(end);
+        self.poll()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameDriver, FrameSchedule};
+    use crate::{LocalClock, LocalDt, LocalTime};
+
+    fn run(driver: &mut FrameDriver<u32>, clock: &LocalClock, secs: f64) -> Vec<(u32, LocalDt)> {
+        let end = LocalTime::from_secs(secs);
+        let mut frames = Vec::new();
+
+        while clock.local_time() < end {
+            frames.extend(driver.advance_until(end));
+        }
+
+        frames
+    }
+
+    fn dts(frames: &[(u32, LocalDt)], key: u32) -> Vec<LocalDt> {
+        frames
+            .iter()
+            .filter(|(frame_key, _)| *frame_key == key)
+            .map(|(_, dt)| *dt)
+            .collect()
+    }
+
+    #[test]
+    fn test_fixed_rate() {
+        let clock = LocalClock::new();
+        let mut driver = FrameDriver::new(clock.clone());
+        driver.add_node(0, FrameSchedule::fixed_hz(64.0), clock.clone());
+        driver.add_node(1, FrameSchedule::fixed_hz(16.0), clock.clone());
+
+        // Frames happen at the start and at the end of the interval.
+        let frames = run(&mut driver, &clock, 2.0);
+        let dts0 = dts(&frames, 0);
+        let dts1 = dts(&frames, 1);
+
+        assert_eq!(dts0.len(), 129);
+        assert_eq!(dts1.len(), 33);
+        assert_eq!(dts0[0], LocalDt::zero());
+        assert!(dts0[1..].iter().all(|dt| *dt == LocalDt::from_hz(64.0)));
+        assert!(dts1[1..].iter().all(|dt| *dt == LocalDt::from_hz(16.0)));
+    }
+
+    #[test]
+    fn test_hitches() {
+        let clock = LocalClock::new();
+        let mut driver = FrameDriver::new(clock.clone());
+        let schedule = FrameSchedule::fixed_hz(64.0)
+            .with_hitches(LocalDt::from_secs(0.25), LocalDt::from_secs(0.125));
+        driver.add_node(0, schedule, clock.clone());
+
+        // The frames at 0.25, 0.5 and 0.75 are followed by a hitch. The hitch
+        // after the frame at 1.0 is not observed within the interval.
+        let dts = dts(&run(&mut driver, &clock, 1.0), 0);
+        let hitch_dt = LocalDt::from_hz(64.0) + LocalDt::from_secs(0.125);
+
+        assert_eq!(dts.iter().filter(|dt| **dt == hitch_dt).count(), 3);
+        assert_eq!(dts.len(), 65 - 3 * 8);
+    }
+
+    #[test]
+    fn test_variable_rate() {
+        let clock = LocalClock::new();
+        let mut driver = FrameDriver::new(clock.clone());
+        let schedule = FrameSchedule::variable_hz(60.0, LocalDt::from_millis(2.0));
+        driver.add_node(0, schedule, clock.clone());
+
+        let dts = dts(&run(&mut driver, &clock, 10.0), 0);
+
+        assert!(dts.len() > 570 && dts.len() < 630, "{}", dts.len());
+        assert!(dts[1..].iter().any(|dt| *dt != dts[1]));
+        assert!(dts[1..]
+            .iter()
+            .all(|dt| *dt >= LocalDt::from_hz(60.0) * 0.1));
+    }
+}
@@ -0,0 +1,41 @@
+use std::collections::BTreeMap;
+
+use crate::{GameDt, PlayerId};
+
+/// A game that can be run by the [simulation harness](super::run_harness).
+pub trait SimGame: Clone {
+    type Input: Clone + Default;
+
+    /// Advance the game by one tick, given the current input of each player.
+    fn run_tick(&mut self, dt: GameDt, inputs: &BTreeMap<PlayerId, Self::Input>);
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "scenario", derive(serde::Serialize, serde::Deserialize))]
+pub struct MoveInput {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// A minimal game in which players move around freely. This is useful for
+/// testing netcode without needing a real game.
+#[derive(Debug, Clone, Default)]
+pub struct MoveGame {
+    pub positions: BTreeMap<PlayerId, (f64, f64)>,
+}
+
+impl MoveGame {
+    pub const MOVE_SPEED: f64 = 100.0;
+}
+
+impl SimGame for MoveGame {
+    type Input = MoveInput;
+
+    fn run_tick(&mut self, dt: GameDt, inputs: &BTreeMap<PlayerId, MoveInput>) {
+        for (player, input) in inputs.iter() {
+            let pos = self.positions.entry(*player).or_insert((0.0, 0.0));
+            pos.0 += input.x * Self::MOVE_SPEED * dt.to_secs();
+            pos.1 += input.y * Self::MOVE_SPEED * dt.to_secs();
+        }
+    }
+}
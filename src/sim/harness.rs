@@ -0,0 +1,483 @@
+use std::{collections::BTreeMap, rc::Rc};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    metrics::Gauge,
+    mock::{MockNet, MockSocketConditions, MockSocketParams},
+    net::{PacketAcks, PacketHeader},
+    transport::{ClientTransport, ServerTransport},
+    ClockSkew, DejitterBuffer, GameDt, GameTime, LocalClock, LocalDt, LocalTime, Metrics,
+    PeriodicTimer, PlaybackClockParams, PlayerId, TickNum, TickPlayback, TickPlaybackParams,
+};
+
+use super::{FrameDriver, FrameSchedule, SimGame};
+
+type Tick<G> = (TickNum, GameTime, G);
+type ServerMsg<G> = (PacketHeader, Tick<G>);
+type ClientMsg<I> = (PacketHeader, TickNum, I);
+
+#[derive(Clone)]
+pub struct HarnessClientParams<I> {
+    /// Name of the client, used as a prefix for its metrics.
+    pub name: String,
+    pub socket: MockSocketConditions,
+    pub frame_schedule: FrameSchedule,
+    pub clock_skew: ClockSkew,
+
+    /// Determines the input of the client, given its current playback time.
+    pub input: Rc<dyn Fn(GameTime) -> I>,
+}
+
+impl<I> HarnessClientParams<I>
+where
+    I: Default,
+{
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            socket: MockSocketConditions::constant(MockSocketParams::perfect()),
+            frame_schedule: FrameSchedule::fixed_hz(60.0),
+            clock_skew: ClockSkew::none(),
+            input: Rc::new(|_| I::default()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HarnessParams<I> {
+    pub duration: LocalDt,
+    pub tick_dt: GameDt,
+
+    /// The server sends the game state to clients every `ticks_per_send`
+    /// ticks.
+    pub ticks_per_send: usize,
+
+    /// Delay of the buffer in which the server keeps client inputs.
+    pub input_delay: LocalDt,
+
+    pub server_frame_schedule: FrameSchedule,
+    pub playback: TickPlaybackParams,
+
+    /// If set, clients continuously set their playback delay from the
+    /// [`LinkEstimate`](crate::net::LinkEstimate) of their connection,
+    /// instead of keeping the delay of `playback`. The estimates are
+    /// recorded as gauges named `{name}_link_*` in any case.
+    pub adaptive_delay: bool,
+
+    /// If set, the [`MockNet`] traffic statistics of each client are
+    /// recorded as gauges named `{name}_server_out_*` and
+    /// `{name}_client_out_*`.
+    pub record_net_stats: bool,
+
+    /// If set, all randomness of the run is derived from this seed, so that
+    /// the run can be reproduced exactly.
+    pub seed: Option<u64>,
+
+    pub clients: Vec<HarnessClientParams<I>>,
+}
+
+impl<I> HarnessParams<I> {
+    pub fn new(tick_dt: GameDt, ticks_per_send: usize) -> Self {
+        Self {
+            duration: LocalDt::from_secs(10.0),
+            tick_dt,
+            ticks_per_send,
+            input_delay: tick_dt.to_local_dt(),
+            server_frame_schedule: FrameSchedule::fixed_hz(60.0),
+            playback: TickPlaybackParams {
+                playback_clock_params: PlaybackClockParams::for_interpolation(
+                    tick_dt * ticks_per_send as f64,
+                ),
+                max_residual: GameDt::from_secs(1.0),
+            },
+            adaptive_delay: false,
+            record_net_stats: false,
+            seed: None,
+            clients: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GaugeSummary {
+    pub len: usize,
+    pub mean: f64,
+    pub std: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl GaugeSummary {
+    pub fn new(gauge: &Gauge) -> Self {
+        Self {
+            len: gauge.len(),
+            mean: gauge.mean(),
+            std: gauge.std(),
+            min: gauge.min(),
+            max: gauge.max(),
+        }
+    }
+}
+
+/// The result of a simulation run.
+#[derive(Debug, Clone)]
+pub struct HarnessReport {
+    /// All metrics that were recorded during the run. No samples are
+    /// discarded, so the metrics cover the whole run.
+    pub metrics: Metrics,
+
+    pub num_server_ticks: usize,
+    pub num_client_ticks: BTreeMap<String, usize>,
+}
+
+impl HarnessReport {
+    pub fn gauge_summaries(&self) -> BTreeMap<String, GaugeSummary> {
+        self.metrics
+            .gauges()
+            .map(|(name, gauge)| (name.clone(), GaugeSummary::new(gauge)))
+            .collect()
+    }
+
+    pub fn gauge_summary(&self, name: &str) -> Option<GaugeSummary> {
+        self.metrics.get_gauge(name).map(GaugeSummary::new)
+    }
+}
+
+struct Server<G: SimGame> {
+    game: G,
+    game_time: GameTime,
+    tick_num: TickNum,
+    tick_timer: PeriodicTimer,
+    ticks_per_send: usize,
+    inputs: BTreeMap<PlayerId, DejitterBuffer<G::Input>>,
+    last_inputs: BTreeMap<PlayerId, G::Input>,
+    acks: BTreeMap<PlayerId, PacketAcks>,
+}
+
+struct Client<G: SimGame> {
+    id: PlayerId,
+    name: String,
+    playback: TickPlayback<Tick<G>>,
+    acks: PacketAcks,
+
+    /// The interval at which the server sends ticks, if the playback delay
+    /// is adapted to the link.
+    adaptive_send_dt: Option<GameDt>,
+
+    input: Rc<dyn Fn(GameTime) -> G::Input>,
+    num_ticks: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Node {
+    Server,
+    Client(usize),
+}
+
+impl<G: SimGame> Server<G> {
+    fn update<T>(&mut self, dt: LocalDt, transport: &mut T)
+    where
+        T: ServerTransport<ServerMsg<G>, ClientMsg<G::Input>>,
+    {
+        for (receive_time, sender, (header, input_num, input)) in transport.receive() {
+            // Duplicates are discarded.
+            let is_new = self
+                .acks
+                .get_mut(&sender)
+                .is_some_and(|acks| acks.receive_header(header));
+
+            if is_new {
+                if let Some(inputs) = self.inputs.get_mut(&sender) {
+                    inputs.insert(receive_time, input_num, input);
+                }
+            }
+        }
+        for acks in self.acks.values_mut() {
+            acks.poll_notifications();
+        }
+
+        self.tick_timer.advance(dt);
+
+        while self.tick_timer.trigger() {
+            for (player, inputs) in self.inputs.iter_mut() {
+                while let Some((_, input)) = inputs.pop() {
+                    self.last_inputs.insert(*player, input);
+                }
+            }
+
+            let dt = self.tick_timer.period().to_game_dt();
+            self.game.run_tick(dt, &self.last_inputs);
+
+            if self.tick_num.to_usize() % self.ticks_per_send == 0 {
+                for (player, acks) in self.acks.iter_mut() {
+                    let tick = (self.tick_num, self.game_time, self.game.clone());
+                    transport.send(*player, (acks.send_header(), tick));
+                }
+            }
+
+            self.game_time += dt;
+            self.tick_num = self.tick_num.succ();
+        }
+    }
+
+    fn game_time(&self) -> GameTime {
+        self.game_time + self.tick_timer.accumulator().to_game_dt()
+    }
+}
+
+impl<G: SimGame> Client<G> {
+    fn update<T>(&mut self, dt: LocalDt, transport: &mut T)
+    where
+        T: ClientTransport<ServerMsg<G>, ClientMsg<G::Input>>,
+    {
+        for (receive_time, (header, tick)) in transport.receive() {
+            if self.acks.receive_header(header) {
+                self.playback.record_tick(receive_time, tick.1, tick);
+            }
+        }
+        self.acks.poll_notifications();
+
+        if let Some(send_dt) = self.adaptive_send_dt {
+            if let Some(delay) = self.acks.estimate().playback_delay(send_dt) {
+                self.playback.playback_clock_params_mut().delay = delay;
+            }
+        }
+
+        let started_ticks = self.playback.advance(dt);
+        self.num_ticks += started_ticks.len();
+
+        let input = (self.input)(self.playback.playback_time());
+        for (_, (tick_num, _, _)) in started_ticks {
+            transport.send((self.acks.send_header(), tick_num, input.clone()));
+        }
+    }
+
+    fn record_metrics(&self, server_time: GameTime, metrics: &mut Metrics) {
+        self.playback.record_metrics(&self.name, metrics);
+        self.acks
+            .record_metrics(&format!("{}_link", self.name), metrics);
+        metrics.record_gauge(
+            &format!("{}_server_delay", self.name),
+            (server_time - self.playback.playback_time()).to_secs(),
+        );
+    }
+}
+
+/// Run a server and clients over a [`MockNet`] for the given duration.
+///
+/// This mirrors the typical setup of the demo: the server ticks the game at a
+/// fixed rate and periodically sends the full game state to all clients. The
+/// clients play back the received ticks with [`TickPlayback`], and send their
+/// input for every tick that they start. The server buffers inputs with a
+/// [`DejitterBuffer`].
+///
+/// The simulation runs headless and as fast as possible, jumping directly from
+/// one frame to the next.
+pub fn run_harness<G: SimGame>(params: &HarnessParams<G::Input>, game: G) -> HarnessReport {
+    let clock = LocalClock::new();
+    let players: Vec<_> = (0..params.clients.len())
+        .map(|index| PlayerId(index as u32))
+        .collect();
+
+    let mut net = MockNet::new(&players, clock.clone());
+    let mut metrics = Metrics::new(params.duration, clock.clone());
+    let mut driver = FrameDriver::new(clock.clone());
+
+    if let Some(seed) = params.seed {
+        let mut rng = StdRng::seed_from_u64(seed);
+        net.set_seed(rng.gen());
+        driver.set_seed(rng.gen());
+    }
+
+    let mut server = Server {
+        game,
+        game_time: GameTime::zero(),
+        tick_num: TickNum::zero(),
+        tick_timer: PeriodicTimer::new(params.tick_dt.to_local_dt()),
+        ticks_per_send: params.ticks_per_send,
+        inputs: players
+            .iter()
+            .map(|player| {
+                let inputs =
+                    DejitterBuffer::new(params.input_delay, params.duration, clock.clone());
+                (*player, inputs)
+            })
+            .collect(),
+        last_inputs: BTreeMap::new(),
+        acks: players
+            .iter()
+            .map(|player| (*player, PacketAcks::new(clock.clone())))
+            .collect(),
+    };
+    driver.add_node(
+        Node::Server,
+        params.server_frame_schedule.clone(),
+        clock.clone(),
+    );
+
+    let mut clients: Vec<Client<G>> = params
+        .clients
+        .iter()
+        .zip(players.iter())
+        .enumerate()
+        .map(|(index, (client_params, player))| {
+            let client_clock = clock.derive(client_params.clock_skew.clone());
+            let adaptive_send_dt = if params.adaptive_delay {
+                Some(params.tick_dt * params.ticks_per_send as f64)
+            } else {
+                None
+            };
+            net.set_conditions(*player, client_params.socket.clone());
+            net.set_client_clock(*player, client_clock.clone());
+            driver.add_node(
+                Node::Client(index),
+                client_params.frame_schedule.clone(),
+                client_clock.clone(),
+            );
+
+            Client {
+                id: *player,
+                name: client_params.name.clone(),
+                playback: TickPlayback::new(params.playback.clone(), client_clock.clone()),
+                acks: PacketAcks::new(client_clock),
+                adaptive_send_dt,
+                input: client_params.input.clone(),
+                num_ticks: 0,
+            }
+        })
+        .collect();
+
+    let end_time = LocalTime::zero() + params.duration;
+
+    while clock.local_time() < end_time {
+        for (node, dt) in driver.advance_until(end_time) {
+            match node {
+                Node::Server => server.update(dt, &mut net),
+                Node::Client(index) => {
+                    let client = &mut clients[index];
+                    client.update(dt, &mut net.client(client.id));
+                    client.record_metrics(server.game_time(), &mut metrics);
+
+                    if params.record_net_stats {
+                        net.stats(client.id)
+                            .record_metrics(&client.name, &mut metrics);
+                    }
+                }
+            }
+        }
+    }
+
+    HarnessReport {
+        metrics,
+        num_server_ticks: server.tick_num.to_usize(),
+        num_client_ticks: clients
+            .iter()
+            .map(|client| (client.name.clone(), client.num_ticks))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::{run_harness, HarnessClientParams, HarnessParams};
+    use crate::{
+        mock::{MockChannelParams, MockConditions, MockSocketConditions},
+        sim::{FrameSchedule, MoveGame, MoveInput},
+        GameDt, LocalDt,
+    };
+
+    #[test]
+    fn test_perfect_network() {
+        let mut params = HarnessParams::new(GameDt::from_hz(60.0), 3);
+        params.duration = LocalDt::from_secs(10.0);
+        params.clients = vec![
+            HarnessClientParams::new("anja"),
+            HarnessClientParams {
+                input: Rc::new(|_| MoveInput { x: 1.0, y: 0.0 }),
+                ..HarnessClientParams::new("brad")
+            },
+        ];
+
+        let report = run_harness(&params, MoveGame::default());
+
+        assert!(report.num_server_ticks >= 599);
+        assert_eq!(report.gauge_summary("brad_link_loss").unwrap().max, 0.0);
+        assert!(report.num_client_ticks["brad"] > 150);
+
+        // Playback should settle at the configured delay behind the stream.
+        let delay = params.playback.playback_clock_params.delay.to_secs();
+        let stream_delay = report.gauge_summary("brad_stream_delay").unwrap();
+        assert!((stream_delay.mean - delay).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_adaptive_delay() {
+        let channel = MockChannelParams {
+            latency_mean: LocalDt::from_millis(50.0),
+            latency_std_dev: LocalDt::from_millis(5.0),
+            loss: 0.2,
+            ..MockChannelParams::perfect()
+        };
+
+        let mut params = HarnessParams::new(GameDt::from_hz(60.0), 3);
+        params.duration = LocalDt::from_secs(20.0);
+        params.seed = Some(1);
+        params.clients = vec![HarnessClientParams {
+            socket: MockSocketConditions::symmetric(MockConditions::constant(channel)),
+            ..HarnessClientParams::new("anja")
+        }];
+
+        let fixed = run_harness(&params, MoveGame::default());
+        params.adaptive_delay = true;
+        let adaptive = run_harness(&params, MoveGame::default());
+
+        // At 20% loss, the adaptive delay covers two lost sends on top of the
+        // fixed delay of two send intervals.
+        let fixed_delay = fixed.gauge_summary("anja_stream_delay").unwrap();
+        let adaptive_delay = adaptive.gauge_summary("anja_stream_delay").unwrap();
+        assert!(fixed_delay.mean < 0.12, "{:?}", fixed_delay);
+        assert!(adaptive_delay.mean > 0.17, "{:?}", adaptive_delay);
+
+        let loss = adaptive.gauge_summary("anja_link_loss").unwrap();
+        assert!((loss.max - 0.2).abs() < 0.1, "{:?}", loss);
+    }
+
+    #[test]
+    fn test_seed() {
+        let channel = MockChannelParams {
+            latency_mean: LocalDt::from_millis(50.0),
+            latency_std_dev: LocalDt::from_millis(10.0),
+            loss: 0.1,
+            ..MockChannelParams::perfect()
+        };
+
+        let mut params = HarnessParams::new(GameDt::from_hz(60.0), 3);
+        params.duration = LocalDt::from_secs(5.0);
+        params.server_frame_schedule = FrameSchedule::variable_hz(60.0, LocalDt::from_millis(2.0));
+        params.seed = Some(7);
+        params.clients = vec![HarnessClientParams {
+            socket: MockSocketConditions::symmetric(MockConditions::constant(channel)),
+            frame_schedule: FrameSchedule::variable_hz(30.0, LocalDt::from_millis(4.0)),
+            ..HarnessClientParams::new("anja")
+        }];
+
+        // Runs with the same seed are identical.
+        let run = |params: &HarnessParams<MoveInput>| {
+            let report = run_harness(params, MoveGame::default());
+            format!("{:?}", report.gauge_summaries())
+        };
+        assert_eq!(run(&params), run(&params));
+
+        params.seed = Some(8);
+        assert_ne!(
+            run(&params),
+            run(&HarnessParams {
+                seed: Some(7),
+                ..params.clone()
+            })
+        );
+    }
+}
@@ -0,0 +1,20 @@
+mod frame;
+mod game;
+mod harness;
+mod quality;
+#[cfg(feature = "scenario")]
+mod scenario;
+mod scheduler;
+mod sweep;
+
+pub use frame::{FrameDriver, FrameSchedule, FrameTimer};
+pub use game::{MoveGame, MoveInput, SimGame};
+pub use harness::{run_harness, GaugeSummary, HarnessClientParams, HarnessParams, HarnessReport};
+pub use quality::{assert_quality, check_quality, QualityCheck, QualityViolation};
+#[cfg(feature = "scenario")]
+pub use scenario::{
+    InputKeyframe, Scenario, ScenarioChannel, ScenarioError, ScenarioEvent, ScenarioFrames,
+    ScenarioNetwork, ScenarioPlayback, ScenarioPlayer, ScenarioWarp,
+};
+pub use scheduler::Scheduler;
+pub use sweep::{run_sweep, PlaybackSweep, SweepQuality, SweepReport, SweepResult};
@@ -0,0 +1,223 @@
+use std::fmt;
+
+use crate::{GameDt, LocalDt, Metrics};
+
+/// A condition on the recorded [`Metrics`] of a simulation run.
+///
+/// Checks refer to gauges by name. The gauges recorded by the
+/// [harness](super::run_harness) are prefixed with the client's name, e.g.
+/// `anja_server_delay`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QualityCheck {
+    /// The gauge is below `threshold` for at least the given fraction of
+    /// time.
+    BelowFor {
+        gauge: String,
+        threshold: f64,
+        fraction: f64,
+    },
+
+    /// The gauge never exceeds `max`.
+    AtMost { gauge: String, max: f64 },
+}
+
+impl QualityCheck {
+    /// The client's playback stays less than `max_delay` behind the server
+    /// for at least the given fraction of time.
+    pub fn playback_delay_below(client: &str, max_delay: GameDt, fraction: f64) -> Self {
+        QualityCheck::BelowFor {
+            gauge: format!("{}_server_delay", client),
+            threshold: max_delay.to_secs(),
+            fraction,
+        }
+    }
+
+    /// The client's playback has to jump ahead at most `max_jumps` times.
+    pub fn max_jumps(client: &str, max_jumps: usize) -> Self {
+        QualityCheck::AtMost {
+            gauge: format!("{}_num_jumps", client),
+            max: max_jumps as f64,
+        }
+    }
+
+    /// The client never goes longer than `max_starvation` without starting a
+    /// tick.
+    pub fn max_tick_starvation(client: &str, max_starvation: LocalDt) -> Self {
+        QualityCheck::AtMost {
+            gauge: format!("{}_tick_starvation", client),
+            max: max_starvation.to_secs(),
+        }
+    }
+
+    pub fn gauge(&self) -> &str {
+        match self {
+            QualityCheck::BelowFor { gauge, .. } => gauge,
+            QualityCheck::AtMost { gauge, .. } => gauge,
+        }
+    }
+
+    pub fn evaluate(&self, metrics: &Metrics) -> Result<(), QualityViolation> {
+        let gauge = metrics
+            .get_gauge(self.gauge())
+            .filter(|gauge| gauge.len() > 0);
+        let gauge = match gauge {
+            Some(gauge) => gauge,
+            None => {
+                return Err(QualityViolation {
+                    check: self.clone(),
+                    actual: None,
+                })
+            }
+        };
+
+        let (ok, actual) = match self {
+            QualityCheck::BelowFor {
+                threshold,
+                fraction,
+                ..
+            } => {
+                let actual = gauge.time_fraction(|value| value < *threshold);
+                (actual >= *fraction, actual)
+            }
+            QualityCheck::AtMost { max, .. } => {
+                let actual = gauge.max();
+                (actual <= *max, actual)
+            }
+        };
+
+        if ok {
+            Ok(())
+        } else {
+            Err(QualityViolation {
+                check: self.clone(),
+                actual: Some(actual),
+            })
+        }
+    }
+}
+
+impl fmt::Display for QualityCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QualityCheck::BelowFor {
+                gauge,
+                threshold,
+                fraction,
+            } => write!(
+                f,
+                "{} < {} for {:.1}% of the time",
+                gauge,
+                threshold,
+                fraction * 100.0
+            ),
+            QualityCheck::AtMost { gauge, max } => write!(f, "{} <= {}", gauge, max),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityViolation {
+    pub check: QualityCheck,
+
+    /// The value that the check observed, or `None` if the gauge was not
+    /// recorded.
+    pub actual: Option<f64>,
+}
+
+impl fmt::Display for QualityViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.check, self.actual) {
+            (check, None) => write!(f, "{}: gauge was not recorded", check),
+            (check @ QualityCheck::BelowFor { .. }, Some(actual)) => {
+                write!(f, "{}: only held {:.1}% of the time", check, actual * 100.0)
+            }
+            (check @ QualityCheck::AtMost { .. }, Some(actual)) => {
+                write!(f, "{}: reached {}", check, actual)
+            }
+        }
+    }
+}
+
+impl std::error::Error for QualityViolation {}
+
+/// Evaluate all checks, returning the ones that failed.
+pub fn check_quality(metrics: &Metrics, checks: &[QualityCheck]) -> Vec<QualityViolation> {
+    checks
+        .iter()
+        .filter_map(|check| check.evaluate(metrics).err())
+        .collect()
+}
+
+/// Panic if any of the checks fails. This is meant to be used in tests.
+pub fn assert_quality(metrics: &Metrics, checks: &[QualityCheck]) {
+    let violations = check_quality(metrics, checks);
+
+    if !violations.is_empty() {
+        let messages: Vec<_> = violations
+            .iter()
+            .map(|violation| format!("  {}", violation))
+            .collect();
+        panic!("Netcode quality checks failed:\n{}", messages.join("\n"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{assert_quality, check_quality, QualityCheck};
+    use crate::{
+        mock::{MockChannelParams, MockSocketConditions, MockSocketParams},
+        sim::{run_harness, HarnessClientParams, HarnessParams, MoveGame, MoveInput},
+        GameDt, LocalDt,
+    };
+
+    fn params(latency: LocalDt, std_dev: LocalDt) -> HarnessParams<MoveInput> {
+        let channel = MockChannelParams {
+            latency_mean: latency,
+            latency_std_dev: std_dev,
+            ..MockChannelParams::perfect()
+        };
+        let socket = MockSocketConditions::constant(MockSocketParams {
+            server_out: channel.clone(),
+            client_out: channel,
+        });
+
+        let mut params = HarnessParams::new(GameDt::from_hz(60.0), 3);
+        params.duration = LocalDt::from_secs(20.0);
+        params.clients = vec![HarnessClientParams {
+            socket,
+            ..HarnessClientParams::new("anja")
+        }];
+        params
+    }
+
+    #[test]
+    fn test_good_network() {
+        let params = params(LocalDt::from_millis(50.0), LocalDt::from_millis(5.0));
+        let report = run_harness(&params, MoveGame::default());
+
+        assert_quality(
+            &report.metrics,
+            &[
+                QualityCheck::playback_delay_below("anja", GameDt::from_millis(200.0), 0.95),
+                QualityCheck::max_jumps("anja", 0),
+                QualityCheck::max_tick_starvation("anja", LocalDt::from_millis(150.0)),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_detects_high_latency() {
+        let params = params(LocalDt::from_millis(300.0), LocalDt::zero());
+        let report = run_harness(&params, MoveGame::default());
+
+        let violations = check_quality(
+            &report.metrics,
+            &[QualityCheck::playback_delay_below(
+                "anja",
+                GameDt::from_millis(200.0),
+                0.95,
+            )],
+        );
+        assert_eq!(violations.len(), 1);
+    }
+}
@@ -0,0 +1,588 @@
+use std::{collections::BTreeSet, fmt, fs, io, path::Path, rc::Rc};
+
+use ron::extensions::Extensions;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    mock::{MockChannelParams, MockConditions, MockSocketConditions},
+    ClockSkew, GameDt, GameTime, LocalDt, LocalTime, TimeWarp,
+};
+
+use super::{FrameSchedule, HarnessClientParams, HarnessParams};
+
+/// A declarative description of a simulation run, which can be loaded from a
+/// RON file and converted into [`HarnessParams`].
+///
+/// All fields have defaults, so scenario files only need to specify what
+/// they change. Times are given in seconds or milliseconds, as indicated by
+/// the field names. For example:
+///
+/// ```ron
+/// (
+///     duration_secs: 20.0,
+///     players: [
+///         (
+///             name: "anja",
+///             network: (
+///                 server_out: (latency_ms: 50.0, jitter_ms: 5.0, loss: 0.01),
+///                 client_out: (latency_ms: 50.0, jitter_ms: 5.0),
+///                 events: [Outage(start_secs: 5.0, duration_secs: 0.5)],
+///             ),
+///             input: [(time_secs: 1.0, input: (x: 1.0, y: 0.0))],
+///         ),
+///     ],
+/// )
+/// ```
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, bound(deserialize = "I: Deserialize<'de> + Default"))]
+pub struct Scenario<I> {
+    pub duration_secs: Option<f64>,
+    pub tick_hz: Option<f64>,
+    pub ticks_per_send: Option<usize>,
+    pub input_delay_ms: Option<f64>,
+    pub server_frames: ScenarioFrames,
+    pub playback: ScenarioPlayback,
+    pub record_net_stats: bool,
+
+    /// Seed for all randomness of the run. Without a seed, every run is
+    /// different.
+    pub seed: Option<u64>,
+
+    pub players: Vec<ScenarioPlayer<I>>,
+}
+
+/// Overrides for the playback params. By default, the params of
+/// [`HarnessParams::new`] are used.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScenarioPlayback {
+    pub delay_ms: Option<f64>,
+    pub max_overtake_ms: Option<f64>,
+    pub max_residual_ms: Option<f64>,
+    pub warp: Option<ScenarioWarp>,
+
+    /// See [`HarnessParams::adaptive_delay`].
+    pub adaptive_delay: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ScenarioWarp {
+    None,
+
+    /// See [`TimeWarp::Sigmoid`]. Fields that are not given are taken from
+    /// [`TimeWarp::sigmoid`].
+    Sigmoid {
+        min_factor: Option<f64>,
+        max_factor: Option<f64>,
+        scale_ms: Option<f64>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScenarioFrames {
+    pub hz: f64,
+    pub jitter_ms: f64,
+    pub hitch_period_secs: Option<f64>,
+    pub hitch_duration_ms: f64,
+}
+
+impl Default for ScenarioFrames {
+    fn default() -> Self {
+        Self {
+            hz: 60.0,
+            jitter_ms: 0.0,
+            hitch_period_secs: None,
+            hitch_duration_ms: 0.0,
+        }
+    }
+}
+
+/// Params of one direction of a player's connection. Defaults to a perfect
+/// channel.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScenarioChannel {
+    pub latency_ms: f64,
+    pub jitter_ms: f64,
+    pub loss: f64,
+    pub loss_correlation: f64,
+}
+
+/// A change of network conditions, applied to both directions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ScenarioEvent {
+    LatencySpike {
+        start_secs: f64,
+        duration_secs: f64,
+        extra_latency_ms: f64,
+    },
+    Outage {
+        start_secs: f64,
+        duration_secs: f64,
+    },
+    RouteChange {
+        time_secs: f64,
+        shift_ms: f64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScenarioNetwork {
+    pub server_out: ScenarioChannel,
+    pub client_out: ScenarioChannel,
+    pub events: Vec<ScenarioEvent>,
+}
+
+/// Sets a player's input, starting at the given playback time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputKeyframe<I> {
+    pub time_secs: f64,
+    pub input: I,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default, bound(deserialize = "I: Deserialize<'de> + Default"))]
+pub struct ScenarioPlayer<I> {
+    /// Prefix of the player's gauges, which must be unique and non-empty.
+    pub name: String,
+    pub network: ScenarioNetwork,
+    pub frames: ScenarioFrames,
+    pub clock_offset_ms: f64,
+    pub clock_drift_ppm: f64,
+
+    /// The player's input is held at the value of the latest keyframe, or
+    /// the default input before the first keyframe.
+    pub input: Vec<InputKeyframe<I>>,
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+
+    /// The scenario parsed, but describes a simulation that can not be run.
+    Invalid(String),
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScenarioError::Io(error) => write!(f, "Failed to read scenario: {}", error),
+            ScenarioError::Parse(error) => write!(f, "Failed to parse scenario: {}", error),
+            ScenarioError::Invalid(message) => write!(f, "Invalid scenario: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+impl From<io::Error> for ScenarioError {
+    fn from(error: io::Error) -> Self {
+        ScenarioError::Io(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for ScenarioError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        ScenarioError::Parse(error)
+    }
+}
+
+/// Returns an error with the given message unless `condition` holds.
+fn ensure(condition: bool, message: impl FnOnce() -> String) -> Result<(), ScenarioError> {
+    if condition {
+        Ok(())
+    } else {
+        Err(ScenarioError::Invalid(message()))
+    }
+}
+
+impl ScenarioFrames {
+    pub fn validate(&self) -> Result<(), ScenarioError> {
+        ensure(self.hz.is_finite() && self.hz > 0.0, || {
+            format!("frame rate must be positive, got {}", self.hz)
+        })?;
+        ensure(self.jitter_ms >= 0.0, || {
+            format!("frame jitter must not be negative, got {}", self.jitter_ms)
+        })?;
+        if let Some(period) = self.hitch_period_secs {
+            ensure(period > 0.0, || {
+                format!("hitch period must be positive, got {}", period)
+            })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn to_frame_schedule(&self) -> FrameSchedule {
+        let schedule = FrameSchedule::variable_hz(self.hz, LocalDt::from_millis(self.jitter_ms));
+
+        match self.hitch_period_secs {
+            Some(period) => schedule.with_hitches(
+                LocalDt::from_secs(period),
+                LocalDt::from_millis(self.hitch_duration_ms),
+            ),
+            None => schedule,
+        }
+    }
+}
+
+impl ScenarioWarp {
+    pub fn validate(&self) -> Result<(), ScenarioError> {
+        match self.to_time_warp() {
+            TimeWarp::None => Ok(()),
+            TimeWarp::Sigmoid {
+                min_factor,
+                max_factor,
+                scale,
+            } => ensure(
+                0.0 < min_factor
+                    && min_factor <= 1.0
+                    && max_factor >= 1.0
+                    && scale > GameDt::zero(),
+                || {
+                    format!(
+                        "warp needs 0 < min_factor <= 1 <= max_factor and a positive scale, \
+                         got {}, {} and {}",
+                        min_factor,
+                        max_factor,
+                        scale.to_secs() * 1000.0,
+                    )
+                },
+            ),
+        }
+    }
+
+    pub fn to_time_warp(&self) -> TimeWarp {
+        match *self {
+            ScenarioWarp::None => TimeWarp::None,
+            ScenarioWarp::Sigmoid {
+                min_factor,
+                max_factor,
+                scale_ms,
+            } => {
+                let mut warp = TimeWarp::sigmoid();
+
+                if let TimeWarp::Sigmoid {
+                    min_factor: warp_min_factor,
+                    max_factor: warp_max_factor,
+                    scale: warp_scale,
+                } = &mut warp
+                {
+                    *warp_min_factor = min_factor.unwrap_or(*warp_min_factor);
+                    *warp_max_factor = max_factor.unwrap_or(*warp_max_factor);
+                    *warp_scale = scale_ms.map_or(*warp_scale, GameDt::from_millis);
+                }
+
+                warp
+            }
+        }
+    }
+}
+
+impl ScenarioChannel {
+    pub fn validate(&self) -> Result<(), ScenarioError> {
+        ensure(self.latency_ms >= 0.0 && self.jitter_ms >= 0.0, || {
+            format!(
+                "latency and jitter must not be negative, got {} and {}",
+                self.latency_ms, self.jitter_ms
+            )
+        })?;
+        ensure(
+            (0.0..=1.0).contains(&self.loss) && (0.0..=1.0).contains(&self.loss_correlation),
+            || {
+                format!(
+                    "loss and loss correlation must be in 0..=1, got {} and {}",
+                    self.loss, self.loss_correlation
+                )
+            },
+        )
+    }
+
+    pub fn to_channel_params(&self) -> MockChannelParams {
+        MockChannelParams {
+            latency_mean: LocalDt::from_millis(self.latency_ms),
+            latency_std_dev: LocalDt::from_millis(self.jitter_ms),
+            loss: self.loss,
+            loss_correlation: self.loss_correlation,
+        }
+    }
+}
+
+impl ScenarioEvent {
+    pub fn apply(&self, conditions: MockConditions) -> MockConditions {
+        match *self {
+            ScenarioEvent::LatencySpike {
+                start_secs,
+                duration_secs,
+                extra_latency_ms,
+            } => conditions.latency_spike(
+                LocalTime::from_secs(start_secs),
+                LocalDt::from_secs(duration_secs),
+                LocalDt::from_millis(extra_latency_ms),
+            ),
+            ScenarioEvent::Outage {
+                start_secs,
+                duration_secs,
+            } => conditions.outage(
+                LocalTime::from_secs(start_secs),
+                LocalDt::from_secs(duration_secs),
+            ),
+            ScenarioEvent::RouteChange {
+                time_secs,
+                shift_ms,
+            } => conditions.route_change(
+                LocalTime::from_secs(time_secs),
+                LocalDt::from_millis(shift_ms),
+            ),
+        }
+    }
+}
+
+impl ScenarioNetwork {
+    pub fn to_socket_conditions(&self) -> MockSocketConditions {
+        let conditions = |channel: &ScenarioChannel| {
+            self.events.iter().fold(
+                MockConditions::constant(channel.to_channel_params()),
+                |conditions, event| event.apply(conditions),
+            )
+        };
+
+        MockSocketConditions {
+            server_out: conditions(&self.server_out),
+            client_out: conditions(&self.client_out),
+        }
+    }
+}
+
+impl<I> ScenarioPlayer<I>
+where
+    I: Clone + Default + 'static,
+{
+    pub fn validate(&self) -> Result<(), ScenarioError> {
+        let context = |error| match error {
+            ScenarioError::Invalid(message) => {
+                ScenarioError::Invalid(format!("player {:?}: {}", self.name, message))
+            }
+            error => error,
+        };
+
+        ensure(!self.name.is_empty(), || {
+            "player name must not be empty".to_string()
+        })?;
+        self.frames.validate().map_err(context)?;
+        self.network.server_out.validate().map_err(context)?;
+        self.network.client_out.validate().map_err(context)?;
+
+        for (i, key) in self.input.iter().enumerate() {
+            ensure(key.time_secs.is_finite(), || {
+                format!("input keyframe time must be finite, got {}", key.time_secs)
+            })
+            .map_err(context)?;
+            ensure(
+                i == 0 || self.input[i - 1].time_secs <= key.time_secs,
+                || "input keyframes must be sorted by time".to_string(),
+            )
+            .map_err(context)?;
+        }
+
+        Ok(())
+    }
+
+    /// Convert the player into harness params. The player should have been
+    /// validated before.
+    pub fn to_client_params(&self) -> HarnessClientParams<I> {
+        let keyframes = self.input.clone();
+
+        HarnessClientParams {
+            name: self.name.clone(),
+            socket: self.network.to_socket_conditions(),
+            frame_schedule: self.frames.to_frame_schedule(),
+            clock_skew: ClockSkew::with_offset(
+                LocalDt::from_millis(self.clock_offset_ms),
+                self.clock_drift_ppm,
+            ),
+            input: Rc::new(move |time: GameTime| {
+                keyframes
+                    .iter()
+                    .rev()
+                    .find(|key| key.time_secs <= time.to_secs())
+                    .map_or_else(I::default, |key| key.input.clone())
+            }),
+        }
+    }
+}
+
+impl<I> Scenario<I>
+where
+    I: Clone + Default + for<'de> Deserialize<'de> + 'static,
+{
+    /// Parse a scenario in RON format. Optional fields can be given without
+    /// wrapping them in `Some`.
+    pub fn from_ron_str(s: &str) -> Result<Self, ScenarioError> {
+        let options = ron::Options::default().with_default_extension(Extensions::IMPLICIT_SOME);
+
+        Ok(options.from_str(s)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ScenarioError> {
+        Self::from_ron_str(&fs::read_to_string(path)?)
+    }
+
+    /// Check that the scenario describes a simulation that can be run.
+    pub fn validate(&self) -> Result<(), ScenarioError> {
+        if let Some(tick_hz) = self.tick_hz {
+            ensure(tick_hz.is_finite() && tick_hz > 0.0, || {
+                format!("tick rate must be positive, got {}", tick_hz)
+            })?;
+        }
+        if let Some(ticks_per_send) = self.ticks_per_send {
+            ensure(ticks_per_send > 0, || {
+                "ticks per send must be positive".to_string()
+            })?;
+        }
+        if let Some(duration) = self.duration_secs {
+            ensure(duration.is_finite() && duration > 0.0, || {
+                format!("duration must be positive, got {}", duration)
+            })?;
+        }
+        if let Some(input_delay) = self.input_delay_ms {
+            ensure(input_delay >= 0.0, || {
+                format!("input delay must not be negative, got {}", input_delay)
+            })?;
+        }
+        if let Some(warp) = self.playback.warp {
+            warp.validate()?;
+        }
+
+        self.server_frames.validate()?;
+        let mut names = BTreeSet::new();
+        for player in self.players.iter() {
+            player.validate()?;
+            ensure(names.insert(&player.name), || {
+                format!("player name {:?} is not unique", player.name)
+            })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn to_harness_params(&self) -> Result<HarnessParams<I>, ScenarioError> {
+        self.validate()?;
+
+        let tick_dt = GameDt::from_hz(self.tick_hz.unwrap_or(60.0));
+        let mut params = HarnessParams::new(tick_dt, self.ticks_per_send.unwrap_or(3));
+
+        if let Some(duration) = self.duration_secs {
+            params.duration = LocalDt::from_secs(duration);
+        }
+        if let Some(input_delay) = self.input_delay_ms {
+            params.input_delay = LocalDt::from_millis(input_delay);
+        }
+        params.server_frame_schedule = self.server_frames.to_frame_schedule();
+        params.record_net_stats = self.record_net_stats;
+        params.seed = self.seed;
+        params.adaptive_delay = self.playback.adaptive_delay;
+
+        let playback = &mut params.playback;
+        if let Some(delay) = self.playback.delay_ms {
+            playback.playback_clock_params.delay = GameDt::from_millis(delay);
+        }
+        if let Some(max_overtake) = self.playback.max_overtake_ms {
+            playback.playback_clock_params.max_overtake = GameDt::from_millis(max_overtake);
+        }
+        if let Some(max_residual) = self.playback.max_residual_ms {
+            playback.max_residual = GameDt::from_millis(max_residual);
+        }
+        if let Some(warp) = self.playback.warp {
+            playback.playback_clock_params.warp = warp.to_time_warp();
+        }
+
+        params.clients = self
+            .players
+            .iter()
+            .map(ScenarioPlayer::to_client_params)
+            .collect();
+
+        Ok(params)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Scenario, ScenarioError};
+    use crate::{
+        sim::{run_harness, MoveGame, MoveInput},
+        GameDt, GameTime, LocalDt, LocalTime, TimeWarp,
+    };
+
+    #[test]
+    fn test_load_example() {
+        let scenario: Scenario<MoveInput> =
+            Scenario::from_ron_str(include_str!("../../scenarios/lossy.ron")).unwrap();
+        let params = scenario.to_harness_params().unwrap();
+
+        assert_eq!(params.duration, LocalDt::from_secs(20.0));
+        assert_eq!(params.clients.len(), 2);
+
+        let anja = &params.clients[0];
+        let outage_time = LocalTime::from_secs(5.25);
+        assert_eq!(anja.socket.params_at(outage_time).server_out.loss, 1.0);
+        assert_eq!(
+            (anja.input)(GameTime::from_secs(2.0)),
+            MoveInput { x: 1.0, y: 0.0 }
+        );
+        assert_eq!((anja.input)(GameTime::zero()), MoveInput::default());
+
+        let report = run_harness(&params, MoveGame::default());
+        assert_eq!(report.num_client_ticks.len(), 2);
+    }
+
+    #[test]
+    fn test_validate() {
+        let is_invalid = |s: &str| {
+            let scenario: Scenario<MoveInput> = Scenario::from_ron_str(s).unwrap();
+            matches!(scenario.to_harness_params(), Err(ScenarioError::Invalid(_)))
+        };
+
+        assert!(!is_invalid("()"));
+        assert!(is_invalid("(ticks_per_send: 0)"));
+        assert!(is_invalid("(tick_hz: 0.0)"));
+        assert!(is_invalid("(duration_secs: -1.0)"));
+        assert!(is_invalid("(server_frames: (hz: -60.0))"));
+        assert!(!is_invalid("(players: [(name: \"a\"), (name: \"b\")])"));
+        assert!(is_invalid("(players: [()])"));
+        assert!(is_invalid("(players: [(name: \"a\"), (name: \"a\")])"));
+        assert!(is_invalid("(players: [(name: \"a\", frames: (hz: 0.0))])"));
+        assert!(is_invalid(
+            "(players: [(name: \"a\", network: (server_out: (loss: 2.0)))])"
+        ));
+        assert!(is_invalid(
+            "(players: [(name: \"a\", input: [(time_secs: 2.0, input: (x: 0.0, y: 0.0)), (time_secs: 1.0, input: (x: 0.0, y: 0.0))])])"
+        ));
+        assert!(is_invalid(
+            "(players: [(name: \"a\", input: [(time_secs: NaN, input: (x: 0.0, y: 0.0))])])"
+        ));
+        assert!(is_invalid("(playback: (warp: Sigmoid(min_factor: 2.0)))"));
+    }
+
+    #[test]
+    fn test_warp() {
+        let scenario: Scenario<MoveInput> = Scenario::from_ron_str(
+            "(playback: (warp: Sigmoid(min_factor: 0.8, max_factor: 1.25, scale_ms: 20.0)))",
+        )
+        .unwrap();
+        let params = scenario.to_harness_params().unwrap();
+
+        assert_eq!(
+            params.playback.playback_clock_params.warp,
+            TimeWarp::Sigmoid {
+                min_factor: 0.8,
+                max_factor: 1.25,
+                scale: GameDt::from_millis(20.0),
+            }
+        );
+    }
+}
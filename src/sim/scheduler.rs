@@ -0,0 +1,124 @@
+use crate::{LocalClock, LocalDt, LocalTime, TimeQueue};
+
+/// Drives a discrete-event simulation.
+///
+/// Instead of advancing the clock in small steps, the scheduler jumps the
+/// clock directly to the time of the next event. Events can be anything,
+/// e.g. frames of simulated nodes, timer triggers or the arrival of messages
+/// (see [`MockNet::next_arrival_time`](crate::mock::MockNet::next_arrival_time)).
+#[derive(Debug, Clone)]
+pub struct Scheduler<E> {
+    clock: LocalClock,
+    events: TimeQueue<E>,
+}
+
+impl<E> Scheduler<E> {
+    pub fn new(clock: LocalClock) -> Self {
+        assert!(!clock.is_derived(), "Scheduler needs a root LocalClock");
+
+        Self {
+            clock,
+            events: TimeQueue::new(),
+        }
+    }
+
+    pub fn clock(&self) -> &LocalClock {
+        &self.clock
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Schedule an event at the given root time. Events in the past are due
+    /// immediately.
+    pub fn schedule(&mut self, time: LocalTime, event: E) {
+        self.events.push(time, event);
+    }
+
+    pub fn schedule_in(&mut self, dt: LocalDt, event: E) {
+        let time = self.clock.local_time() + dt;
+        self.schedule(time, event);
+    }
+
+    pub fn next_event_time(&self) -> Option<LocalTime> {
+        self.events.peek_time()
+    }
+
+    /// Pop the next event if it is due at the current time.
+    pub fn pop_due(&mut self) -> Option<E> {
+        self.events
+            .pop_due(self.clock.local_time())
+            .map(|(_, event)| event)
+    }
+
+    /// Advance the clock to the next event, unless that is later than `end`,
+    /// in which case the clock is advanced to `end`.
+    ///
+    /// Returns `false` if there are no more events until `end`.
+    pub fn advance_until(&mut self, end: LocalTime) -> bool {
+        match self.next_event_time() {
+            Some(time) if time <= end => {
+                if time > self.clock.local_time() {
+                    self.clock.set_local_time(time);
+                }
+                true
+            }
+            _ => {
+                if end > self.clock.local_time() {
+                    self.clock.set_local_time(end);
+                }
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Scheduler;
+    use crate::{
+        mock::{MockChannelParams, MockNet, MockSocketParams},
+        LocalClock, LocalDt, LocalTime, PlayerId,
+    };
+
+    #[test]
+    fn test_jump_to_arrivals() {
+        let clock = LocalClock::new();
+        let players: Vec<_> = (0..1000).map(PlayerId).collect();
+        let mut net: MockNet<u32, ()> = MockNet::new(&players, clock.clone());
+        let mut scheduler: Scheduler<()> = Scheduler::new(clock.clone());
+
+        let params = MockChannelParams {
+            latency_mean: LocalDt::from_millis(100.0),
+            ..MockChannelParams::perfect()
+        };
+        for player in players.iter() {
+            net.set_params(
+                *player,
+                MockSocketParams {
+                    server_out: params.clone(),
+                    client_out: params.clone(),
+                },
+            );
+            net.send_to_client(*player, player.to_u32());
+        }
+
+        scheduler.schedule(net.next_arrival_time().unwrap(), ());
+        assert!(scheduler.advance_until(LocalTime::from_secs(600.0)));
+        assert_eq!(scheduler.pop_due(), Some(()));
+        assert_eq!(clock.local_time(), LocalTime::from_secs(0.1));
+
+        for player in players.iter() {
+            assert_eq!(net.receive_from_server(*player).len(), 1);
+        }
+        assert_eq!(net.next_arrival_time(), None);
+
+        assert!(!scheduler.advance_until(LocalTime::from_secs(600.0)));
+        assert_eq!(clock.local_time(), LocalTime::from_secs(600.0));
+    }
+}
@@ -0,0 +1,393 @@
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use rand::Rng;
+
+use crate::{GameDt, TickPlaybackParams, TimeWarp};
+
+use super::{run_harness, HarnessParams, HarnessReport, SimGame};
+
+/// Cost of a single playback jump, in seconds, when computing the roughness
+/// of a run. A jump is about as noticeable as a stall of this length.
+const JUMP_COST: f64 = 0.1;
+
+/// A set of values for each playback parameter that is to be tuned.
+///
+/// [`grid`](PlaybackSweep::grid) produces all combinations of the values,
+/// while [`random`](PlaybackSweep::random) samples uniformly between the
+/// smallest and the largest value of each parameter.
+#[derive(Debug, Clone)]
+pub struct PlaybackSweep {
+    /// Parameters that are not swept are taken from here.
+    pub base: TickPlaybackParams,
+
+    pub delays: Vec<GameDt>,
+    pub max_overtakes: Vec<GameDt>,
+    pub max_residuals: Vec<GameDt>,
+    pub warps: Vec<TimeWarp>,
+}
+
+impl PlaybackSweep {
+    /// Create a sweep that only contains the `base` parameters.
+    pub fn new(base: TickPlaybackParams) -> Self {
+        Self {
+            delays: vec![base.playback_clock_params.delay],
+            max_overtakes: vec![base.playback_clock_params.max_overtake],
+            max_residuals: vec![base.max_residual],
+            warps: vec![base.playback_clock_params.warp],
+            base,
+        }
+    }
+
+    pub fn grid(&self) -> Vec<TickPlaybackParams> {
+        let mut candidates = Vec::new();
+
+        for delay in &self.delays {
+            for max_overtake in &self.max_overtakes {
+                for max_residual in &self.max_residuals {
+                    for warp in &self.warps {
+                        candidates.push(self.candidate(
+                            *delay,
+                            *max_overtake,
+                            *max_residual,
+                            *warp,
+                        ));
+                    }
+                }
+            }
+        }
+
+        candidates
+    }
+
+    pub fn random<R: Rng>(&self, rng: &mut R, num_candidates: usize) -> Vec<TickPlaybackParams> {
+        (0..num_candidates)
+            .map(|_| {
+                let warp = if self.warps.is_empty() {
+                    self.base.playback_clock_params.warp
+                } else {
+                    self.warps[rng.gen_range(0..self.warps.len())]
+                };
+
+                self.candidate(
+                    sample_between(rng, &self.delays, self.base.playback_clock_params.delay),
+                    sample_between(
+                        rng,
+                        &self.max_overtakes,
+                        self.base.playback_clock_params.max_overtake,
+                    ),
+                    sample_between(rng, &self.max_residuals, self.base.max_residual),
+                    warp,
+                )
+            })
+            .collect()
+    }
+
+    fn candidate(
+        &self,
+        delay: GameDt,
+        max_overtake: GameDt,
+        max_residual: GameDt,
+        warp: TimeWarp,
+    ) -> TickPlaybackParams {
+        let mut params = self.base.clone();
+        params.playback_clock_params.delay = delay;
+        params.playback_clock_params.max_overtake = max_overtake;
+        params.playback_clock_params.warp = warp;
+        params.max_residual = max_residual;
+        params
+    }
+}
+
+fn sample_between<R: Rng>(rng: &mut R, values: &[GameDt], default: GameDt) -> GameDt {
+    let min = values
+        .iter()
+        .map(|dt| dt.to_secs())
+        .fold(f64::INFINITY, f64::min);
+    let max = values
+        .iter()
+        .map(|dt| dt.to_secs())
+        .fold(f64::NEG_INFINITY, f64::max);
+
+    if values.is_empty() {
+        default
+    } else if min < max {
+        GameDt::from_secs(rng.gen_range(min..max))
+    } else {
+        GameDt::from_secs(min)
+    }
+}
+
+/// Quality metrics of one run, aggregated over all clients.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepQuality {
+    /// Mean time, in seconds, by which playback is behind the server.
+    pub delay_mean: f64,
+
+    /// Standard deviation of the delay behind the server, in seconds.
+    pub delay_std: f64,
+
+    /// Total number of times that playback had to jump ahead.
+    pub num_jumps: usize,
+
+    /// Longest time, in seconds, that any client went without starting a
+    /// tick.
+    pub max_starvation: f64,
+}
+
+impl SweepQuality {
+    pub fn from_report(report: &HarnessReport) -> Self {
+        let clients: Vec<_> = report.num_client_ticks.keys().collect();
+        let summary =
+            |client: &String, name: &str| report.gauge_summary(&format!("{}_{}", client, name));
+
+        let delays: Vec<_> = clients
+            .iter()
+            .filter_map(|client| summary(client, "server_delay"))
+            .collect();
+        let num_delays = delays.len().max(1) as f64;
+
+        Self {
+            delay_mean: delays.iter().map(|summary| summary.mean).sum::<f64>() / num_delays,
+            delay_std: delays.iter().map(|summary| summary.std).sum::<f64>() / num_delays,
+            num_jumps: clients
+                .iter()
+                .filter_map(|client| summary(client, "num_jumps"))
+                .map(|summary| summary.max as usize)
+                .sum(),
+            max_starvation: clients
+                .iter()
+                .filter_map(|client| summary(client, "tick_starvation"))
+                .map(|summary| summary.max)
+                .fold(0.0, f64::max),
+        }
+    }
+
+    /// How unsmooth playback was, in seconds.
+    pub fn roughness(&self) -> f64 {
+        self.delay_std + self.max_starvation + self.num_jumps as f64 * JUMP_COST
+    }
+
+    /// Weigh latency against roughness. A `latency_weight` of 1 only
+    /// considers latency, a weight of 0 only considers roughness.
+    pub fn cost(&self, latency_weight: f64) -> f64 {
+        latency_weight * self.delay_mean + (1.0 - latency_weight) * self.roughness()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SweepResult {
+    /// Index into the candidates of the sweep.
+    pub candidate: usize,
+
+    /// Index into the network conditions of the sweep.
+    pub network: usize,
+
+    pub quality: SweepQuality,
+}
+
+#[derive(Debug, Clone)]
+pub struct SweepReport {
+    pub candidates: Vec<TickPlaybackParams>,
+    pub num_networks: usize,
+
+    /// Results for every combination of candidate and network, ordered by
+    /// candidate first.
+    pub results: Vec<SweepResult>,
+}
+
+impl SweepReport {
+    /// The cost of a candidate, averaged over all network conditions.
+    pub fn mean_cost(&self, candidate: usize, latency_weight: f64) -> f64 {
+        let costs: Vec<_> = self
+            .results
+            .iter()
+            .filter(|result| result.candidate == candidate)
+            .map(|result| result.quality.cost(latency_weight))
+            .collect();
+
+        costs.iter().sum::<f64>() / costs.len().max(1) as f64
+    }
+
+    /// Returns the candidate with the lowest mean cost for the given
+    /// trade-off between latency and smoothness (see [`SweepQuality::cost`]).
+    ///
+    /// Candidates whose cost is NaN are skipped. Returns `None` if there are
+    /// no candidates or no network conditions to compare them on.
+    pub fn recommend(&self, latency_weight: f64) -> Option<&TickPlaybackParams> {
+        if self.num_networks == 0 {
+            return None;
+        }
+
+        (0..self.candidates.len())
+            .map(|candidate| (candidate, self.mean_cost(candidate, latency_weight)))
+            .filter(|(_, cost)| !cost.is_nan())
+            .min_by(|(_, cost1), (_, cost2)| cost1.partial_cmp(cost2).unwrap())
+            .map(|(candidate, _)| &self.candidates[candidate])
+    }
+
+    /// Format the results as a plain text table. Times are in milliseconds.
+    pub fn table(&self) -> String {
+        let mut table = format!(
+            "{:>4} {:>4} {:>8} {:>8} {:>8} {:>8} {:>10} {:>10} {:>6} {:>10}\n",
+            "cand",
+            "net",
+            "delay",
+            "overtake",
+            "residual",
+            "warp",
+            "delay_mean",
+            "delay_std",
+            "jumps",
+            "starvation",
+        );
+
+        for result in &self.results {
+            let candidate = &self.candidates[result.candidate];
+            let warp = match candidate.playback_clock_params.warp {
+                TimeWarp::None => "none",
+                TimeWarp::Sigmoid { .. } => "sigmoid",
+            };
+
+            writeln!(
+                table,
+                "{:>4} {:>4} {:>8.1} {:>8.1} {:>8.1} {:>8} {:>10.1} {:>10.1} {:>6} {:>10.1}",
+                result.candidate,
+                result.network,
+                candidate.playback_clock_params.delay.to_secs() * 1000.0,
+                candidate.playback_clock_params.max_overtake.to_secs() * 1000.0,
+                candidate.max_residual.to_secs() * 1000.0,
+                warp,
+                result.quality.delay_mean * 1000.0,
+                result.quality.delay_std * 1000.0,
+                result.quality.num_jumps,
+                result.quality.max_starvation * 1000.0,
+            )
+            .unwrap();
+        }
+
+        table
+    }
+}
+
+/// Run the harness for every combination of playback parameter candidate and
+/// network conditions, in parallel on all available cores.
+///
+/// Since the harness parameters are not `Send`, they are built inside of each
+/// thread by `scenario`, given one of the `networks`. The playback parameters
+/// of the scenario are then replaced by the candidate.
+pub fn run_sweep<G, N, F>(
+    candidates: &[TickPlaybackParams],
+    networks: &[N],
+    game: &G,
+    scenario: F,
+) -> SweepReport
+where
+    G: SimGame + Sync,
+    N: Sync,
+    F: Fn(&N) -> HarnessParams<G::Input> + Sync,
+{
+    let num_runs = candidates.len() * networks.len();
+    let num_threads = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(num_runs);
+
+    let next_run = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(num_runs));
+
+    thread::scope(|scope| {
+        for _ in 0..num_threads {
+            scope.spawn(|| loop {
+                let run = next_run.fetch_add(1, Ordering::Relaxed);
+                if run >= num_runs {
+                    break;
+                }
+
+                let (candidate, network) = (run / networks.len(), run % networks.len());
+                let mut params = scenario(&networks[network]);
+                params.playback = candidates[candidate].clone();
+
+                let report = run_harness(&params, game.clone());
+                let result = SweepResult {
+                    candidate,
+                    network,
+                    quality: SweepQuality::from_report(&report),
+                };
+                results.lock().unwrap().push(result);
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|result| (result.candidate, result.network));
+
+    SweepReport {
+        candidates: candidates.to_vec(),
+        num_networks: networks.len(),
+        results,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run_sweep, PlaybackSweep, SweepReport};
+    use crate::{
+        mock::{MockChannelParams, MockSocketConditions, MockSocketParams},
+        sim::{HarnessClientParams, HarnessParams, MoveGame, MoveInput},
+        GameDt, LocalDt,
+    };
+
+    #[test]
+    fn test_recommend_lowest_delay() {
+        let base = HarnessParams::<MoveInput>::new(GameDt::from_hz(60.0), 3).playback;
+        let mut sweep = PlaybackSweep::new(base);
+        sweep.delays = vec![GameDt::from_millis(100.0), GameDt::from_millis(300.0)];
+
+        let networks: Vec<_> = [20.0, 80.0]
+            .iter()
+            .map(|latency| {
+                let channel = MockChannelParams {
+                    latency_mean: LocalDt::from_millis(*latency),
+                    ..MockChannelParams::perfect()
+                };
+                MockSocketParams {
+                    server_out: channel.clone(),
+                    client_out: channel,
+                }
+            })
+            .collect();
+
+        let report = run_sweep(&sweep.grid(), &networks, &MoveGame::default(), |network| {
+            let mut params = HarnessParams::new(GameDt::from_hz(60.0), 3);
+            params.duration = LocalDt::from_secs(5.0);
+            params.clients = vec![HarnessClientParams {
+                socket: MockSocketConditions::constant(network.clone()),
+                ..HarnessClientParams::new("anja")
+            }];
+            params
+        });
+
+        assert_eq!(report.results.len(), 4);
+        assert_eq!(report.table().lines().count(), 5);
+
+        let recommended = report.recommend(1.0).unwrap();
+        assert_eq!(
+            recommended.playback_clock_params.delay,
+            GameDt::from_millis(100.0)
+        );
+        assert!(report.recommend(f64::NAN).is_none());
+
+        let empty = SweepReport {
+            num_networks: 0,
+            results: Vec::new(),
+            ..report
+        };
+        assert!(empty.recommend(1.0).is_none());
+    }
+}
@@ -14,6 +14,10 @@ impl TickNum {
         TickNum(0)
     }
 
+    pub fn from_u64(num: u64) -> Self {
+        TickNum(num)
+    }
+
     pub fn from_tick_time(tick_time: TickTime) -> Self {
         // In TickNum space, we interpret one tick as meaning "one second",
         // although this of course does not match with the actual game time.
@@ -2,7 +2,7 @@ use crate::{
     GameDt, GameTime, LocalClock, LocalDt, LocalTime, Metrics, PlaybackClock, PlaybackClockParams,
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TickPlaybackParams {
     pub playback_clock_params: PlaybackClockParams,
     pub max_residual: GameDt,
@@ -24,6 +24,8 @@ pub struct TickPlayback<T> {
     playback_clock: PlaybackClock,
     ticks: Vec<(GameTime, T)>,
     current_tick: Option<(GameTime, T)>,
+    last_tick_start_time: Option<LocalTime>,
+    num_jumps: usize,
 }
 
 impl<T> TickPlayback<T>
@@ -37,6 +39,8 @@ where
             playback_clock: PlaybackClock::new(params.playback_clock_params, local_clock),
             ticks: Vec::new(),
             current_tick: None,
+            last_tick_start_time: None,
+            num_jumps: 0,
         }
     }
 
@@ -56,6 +60,19 @@ where
         &mut self.playback_clock.params
     }
 
+    /// Returns how often playback had to jump ahead because it fell too far
+    /// behind the tick stream.
+    pub fn num_jumps(&self) -> usize {
+        self.num_jumps
+    }
+
+    /// Returns the local time that has passed since the last tick was
+    /// started, or `None` if no tick has been started yet.
+    pub fn time_since_last_tick(&self) -> Option<LocalDt> {
+        self.last_tick_start_time
+            .map(|start_time| self.local_clock.local_time() - start_time)
+    }
+
     pub fn current_tick(&self) -> Option<(GameTime, &T)> {
         self.current_tick
             .as_ref()
@@ -128,6 +145,7 @@ where
                 );
 
                 self.playback_clock.set_playback_time(*newest_time);
+                self.num_jumps += 1;
             }
 
             while self.ticks.len() > 1 {
@@ -142,6 +160,7 @@ where
 
             started_ticks.push(oldest_tick.clone());
             self.current_tick = Some(oldest_tick);
+            self.last_tick_start_time = Some(self.local_clock.local_time());
         }
 
         started_ticks
@@ -149,6 +168,14 @@ where
 
     pub fn record_metrics(&self, prefix: &str, metrics: &mut Metrics) {
         self.playback_clock.record_metrics(prefix, metrics);
+        metrics.record_gauge(&format!("{}_num_jumps", prefix), self.num_jumps as f64);
+
+        if let Some(time_since_last_tick) = self.time_since_last_tick() {
+            metrics.record_gauge(
+                &format!("{}_tick_starvation", prefix),
+                time_since_last_tick.to_secs(),
+            );
+        }
     }
 
     fn is_oldest_tick_ready(&self) -> bool {
@@ -1,16 +1,24 @@
 use std::{cell::Cell, rc::Rc};
 
-use super::{LocalDt, LocalTime};
+use super::{ClockSkew, LocalDt, LocalTime};
 
 #[derive(Debug, Clone)]
 pub struct LocalClock {
     local_time: Rc<Cell<Option<LocalTime>>>,
+    derived: Option<Rc<DerivedClock>>,
+}
+
+#[derive(Debug)]
+struct DerivedClock {
+    source: LocalClock,
+    skew: ClockSkew,
 }
 
 impl Default for LocalClock {
     fn default() -> Self {
         Self {
             local_time: Rc::new(Cell::new(None)),
+            derived: None,
         }
     }
 }
@@ -20,11 +28,47 @@ impl LocalClock {
         LocalClock::default()
     }
 
+    /// Create a clock that follows this clock, but deviates from it according
+    /// to `skew`. This can be used to simulate machines whose clocks have
+    /// offset and drift.
+    ///
+    /// The time of a derived clock cannot be set directly; it only changes
+    /// when the time of the root clock changes.
+    pub fn derive(&self, skew: ClockSkew) -> LocalClock {
+        LocalClock {
+            local_time: self.local_time.clone(),
+            derived: Some(Rc::new(DerivedClock {
+                source: self.clone(),
+                skew,
+            })),
+        }
+    }
+
+    pub fn is_derived(&self) -> bool {
+        self.derived.is_some()
+    }
+
     pub fn local_time(&self) -> LocalTime {
+        self.local_time_at(self.root_time())
+    }
+
+    /// Returns the time of the root clock that this clock is derived from.
+    pub fn root_time(&self) -> LocalTime {
         self.local_time.get().unwrap_or(LocalTime::zero())
     }
 
+    /// Returns the time that this clock shows when the root clock shows
+    /// `root_time`.
+    pub fn local_time_at(&self, root_time: LocalTime) -> LocalTime {
+        match self.derived.as_ref() {
+            Some(derived) => derived.skew.apply(derived.source.local_time_at(root_time)),
+            None => root_time,
+        }
+    }
+
     pub fn set_local_time(&mut self, new_local_time: LocalTime) -> LocalDt {
+        assert!(!self.is_derived(), "Cannot set time of derived LocalClock");
+
         let dt = self
             .local_time
             .get()
@@ -36,6 +80,32 @@ impl LocalClock {
     }
 
     pub fn advance(&mut self, dt: LocalDt) {
+        assert!(!self.is_derived(), "Cannot advance derived LocalClock");
+
         self.local_time.set(Some(self.local_time() + dt));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::LocalClock;
+    use crate::{time::ClockSkew, LocalDt, LocalTime};
+
+    #[test]
+    fn test_derived_clock() {
+        let mut clock = LocalClock::new();
+        let skew = ClockSkew {
+            offset: LocalDt::from_secs(10.0),
+            drift_ppm: 1000.0,
+            steps: vec![(LocalTime::from_secs(50.0), LocalDt::from_secs(-1.0))],
+        };
+        let derived = clock.derive(skew);
+
+        clock.set_local_time(LocalTime::from_secs(20.0));
+        assert!((derived.local_time().to_secs() - 30.02).abs() < 1e-9);
+
+        clock.advance(LocalDt::from_secs(80.0));
+        assert!((derived.local_time().to_secs() - 109.1).abs() < 1e-9);
+        assert_eq!(derived.root_time(), LocalTime::from_secs(100.0));
+    }
+}
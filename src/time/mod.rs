@@ -1,13 +1,19 @@
 mod local;
 mod periodic;
 mod playback;
+mod queue;
 mod samples;
+mod skew;
 mod stream;
+mod sync;
 mod time;
 
 pub use local::LocalClock;
 pub use periodic::PeriodicTimer;
-pub use playback::{PlaybackClock, PlaybackClockParams};
+pub use playback::{PlaybackClock, PlaybackClockParams, TimeWarp};
+pub use queue::TimeQueue;
 pub use samples::Samples;
+pub use skew::ClockSkew;
 pub use stream::predict_stream_time;
+pub use sync::SyncClock;
 pub use time::{Dt, GameDt, GameTag, GameTime, LocalDt, LocalTag, LocalTime, Time, TimeTag};
@@ -26,6 +26,11 @@ impl PeriodicTimer {
         self.accumulator / self.period
     }
 
+    /// Returns the time that needs to pass until the timer triggers next.
+    pub fn time_until_trigger(&self) -> LocalDt {
+        (self.period - self.accumulator).max(LocalDt::zero())
+    }
+
     pub fn advance(&mut self, dt: LocalDt) {
         self.accumulator += dt;
     }
@@ -2,15 +2,55 @@ use crate::Metrics;
 
 use super::{predict_stream_time, GameDt, GameTime, LocalClock, LocalDt, LocalTime, Samples};
 
-pub fn time_warp(residual: GameDt) -> f64 {
-    0.5 + (2.0 - 0.5) / (1.0 + 2.0 * (-residual.to_secs() / 0.005).exp())
+/// How a [`PlaybackClock`] changes its speed to catch up with its target
+/// time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeWarp {
+    /// Always play back at real-time speed, never catching up.
+    None,
+
+    /// Smoothly vary the speed between `min_factor` and `max_factor`,
+    /// depending on how far playback is behind its target. `scale` determines
+    /// how sharply the speed reacts to the residual.
+    Sigmoid {
+        min_factor: f64,
+        max_factor: f64,
+        scale: GameDt,
+    },
 }
 
-#[derive(Debug, Clone)]
+impl TimeWarp {
+    /// The default warp, which plays back at between half and double speed.
+    pub fn sigmoid() -> Self {
+        TimeWarp::Sigmoid {
+            min_factor: 0.5,
+            max_factor: 2.0,
+            scale: GameDt::from_secs(0.005),
+        }
+    }
+
+    pub fn factor(&self, residual: GameDt) -> f64 {
+        match self {
+            TimeWarp::None => 1.0,
+            TimeWarp::Sigmoid {
+                min_factor,
+                max_factor,
+                scale,
+            } => {
+                min_factor
+                    + (max_factor - min_factor)
+                        / (1.0 + 2.0 * (-residual.to_secs() / scale.to_secs()).exp())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct PlaybackClockParams {
     pub delay: GameDt,
     pub max_overtake: GameDt,
     pub max_sample_age: LocalDt,
+    pub warp: TimeWarp,
 }
 
 impl PlaybackClockParams {
@@ -19,6 +59,7 @@ impl PlaybackClockParams {
             delay: tick_send_dt * 2.0,
             max_overtake: GameDt::zero(),
             max_sample_age: LocalDt::from_secs(5.0),
+            warp: TimeWarp::sigmoid(),
         }
     }
 }
@@ -71,7 +112,7 @@ impl PlaybackClock {
             .unwrap_or(GameTime::zero());
         let max_playback_time = max_stream_time + self.params.max_overtake;
 
-        self.playback_time += dt.to_game_dt() * time_warp(residual);
+        self.playback_time += dt.to_game_dt() * self.params.warp.factor(residual);
         self.playback_time = self.playback_time.min(max_playback_time);
 
         residual
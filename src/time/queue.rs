@@ -0,0 +1,98 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use super::LocalTime;
+
+#[derive(Debug, Clone)]
+struct Entry<T> {
+    time: LocalTime,
+    seq: u64,
+    value: T,
+}
+
+/// A priority queue of values that become due at some point in time.
+///
+/// Values with the same time are returned in the order in which they were
+/// pushed.
+#[derive(Debug, Clone)]
+pub struct TimeQueue<T> {
+    entries: BinaryHeap<Entry<T>>,
+    next_seq: u64,
+}
+
+impl<T> Default for TimeQueue<T> {
+    fn default() -> Self {
+        Self {
+            entries: BinaryHeap::new(),
+            next_seq: 0,
+        }
+    }
+}
+
+impl<T> TimeQueue<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn push(&mut self, time: LocalTime, value: T) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        self.entries.push(Entry { time, seq, value });
+    }
+
+    /// Returns the time of the earliest value in the queue.
+    pub fn peek_time(&self) -> Option<LocalTime> {
+        self.entries.peek().map(|entry| entry.time)
+    }
+
+    pub fn pop(&mut self) -> Option<(LocalTime, T)> {
+        self.entries.pop().map(|entry| (entry.time, entry.value))
+    }
+
+    /// Pop the earliest value if it is due at time `now`.
+    pub fn pop_due(&mut self, now: LocalTime) -> Option<(LocalTime, T)> {
+        if self.peek_time().is_some_and(|time| time <= now) {
+            self.pop()
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> PartialOrd for Entry<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(&other))
+    }
+}
+
+impl<T> Eq for Entry<T> {}
+
+impl<T> PartialEq for Entry<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(&other) == Ordering::Equal
+    }
+}
+
+impl<T> Ord for Entry<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap, so we reverse the order to get the earliest
+        // entry first.
+        other
+            .time
+            .partial_cmp(&self.time)
+            .unwrap()
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
@@ -0,0 +1,86 @@
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+
+use super::{LocalDt, LocalTime};
+
+/// Describes how a derived clock deviates from its source clock.
+#[derive(Debug, Clone)]
+pub struct ClockSkew {
+    /// Constant offset that is added to the source time.
+    pub offset: LocalDt,
+
+    /// Rate at which the derived clock drifts away from the source clock, in
+    /// parts per million.
+    pub drift_ppm: f64,
+
+    /// Step adjustments of the derived clock, e.g. caused by NTP. Each step is
+    /// given by the source time at which it happens and its size.
+    pub steps: Vec<(LocalTime, LocalDt)>,
+}
+
+impl ClockSkew {
+    pub fn none() -> Self {
+        Self {
+            offset: LocalDt::zero(),
+            drift_ppm: 0.0,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn with_offset(offset: LocalDt, drift_ppm: f64) -> Self {
+        Self {
+            offset,
+            drift_ppm,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Add randomly occurring steps up to the source time `until`.
+    ///
+    /// The time between steps is uniformly distributed in `[0, 2 *
+    /// mean_interval]`, and the step sizes are normally distributed around
+    /// zero.
+    pub fn with_random_steps<R: Rng>(
+        mut self,
+        rng: &mut R,
+        until: LocalTime,
+        mean_interval: LocalDt,
+        step_std_dev: LocalDt,
+    ) -> Self {
+        assert!(
+            mean_interval > LocalDt::zero(),
+            "Step interval must be positive"
+        );
+        assert!(
+            step_std_dev >= LocalDt::zero(),
+            "Step standard deviation must not be negative"
+        );
+
+        let distribution = Normal::new(0.0, step_std_dev.to_secs()).unwrap();
+        let mut time = LocalTime::zero();
+
+        loop {
+            time += mean_interval * rng.gen_range(0.0..2.0);
+            if time > until {
+                break;
+            }
+
+            let step = LocalDt::from_secs(distribution.sample(rng));
+            self.steps.push((time, step));
+        }
+
+        self
+    }
+
+    /// Map a time of the source clock to a time of the derived clock.
+    pub fn apply(&self, source_time: LocalTime) -> LocalTime {
+        let drift = source_time.to_dt() * (self.drift_ppm * 1e-6);
+        let steps = self
+            .steps
+            .iter()
+            .filter(|(step_time, _)| *step_time <= source_time)
+            .fold(LocalDt::zero(), |sum, (_, step)| sum + *step);
+
+        source_time + self.offset + drift + steps
+    }
+}
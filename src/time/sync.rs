@@ -0,0 +1,69 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use super::{LocalDt, LocalTime};
+
+#[derive(Debug)]
+enum Source {
+    // The time in seconds, stored as the bits of an `f64`.
+    Manual(AtomicU64),
+    RealTime(Instant),
+}
+
+/// A clock that can be shared between threads.
+///
+/// In contrast to [`LocalClock`](super::LocalClock), this can be used by
+/// server and clients that run on separate threads in one process. The clock
+/// is either advanced manually, or follows real time.
+#[derive(Debug, Clone)]
+pub struct SyncClock(Arc<Source>);
+
+impl Default for SyncClock {
+    fn default() -> Self {
+        Self(Arc::new(Source::Manual(AtomicU64::new(0.0f64.to_bits()))))
+    }
+}
+
+impl SyncClock {
+    /// Create a clock that starts at zero and is advanced manually.
+    pub fn new() -> Self {
+        SyncClock::default()
+    }
+
+    /// Create a clock that shows the real time that has passed since its
+    /// creation.
+    pub fn real_time() -> Self {
+        Self(Arc::new(Source::RealTime(Instant::now())))
+    }
+
+    pub fn is_real_time(&self) -> bool {
+        matches!(*self.0, Source::RealTime(_))
+    }
+
+    pub fn local_time(&self) -> LocalTime {
+        match &*self.0 {
+            Source::Manual(bits) => {
+                LocalTime::from_secs(f64::from_bits(bits.load(Ordering::SeqCst)))
+            }
+            Source::RealTime(start) => LocalTime::from_secs(start.elapsed().as_secs_f64()),
+        }
+    }
+
+    pub fn set_local_time(&self, local_time: LocalTime) {
+        match &*self.0 {
+            Source::Manual(bits) => bits.store(local_time.to_secs().to_bits(), Ordering::SeqCst),
+            Source::RealTime(_) => panic!("Cannot set time of real-time SyncClock"),
+        }
+    }
+
+    /// Advance a manual clock. This is not atomic, so only one thread should
+    /// advance the clock.
+    pub fn advance(&self, dt: LocalDt) {
+        self.set_local_time(self.local_time() + dt);
+    }
+}
@@ -0,0 +1,275 @@
+use std::{
+    collections::BTreeMap,
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{mock::MockChannelParams, LocalTime, PlayerId, SyncClock, TimeQueue};
+
+use super::{ClientTransport, ServerTransport};
+
+/// One direction of a channel transport, optionally impaired.
+struct Link<T> {
+    clock: SyncClock,
+    params: Option<MockChannelParams>,
+    last_lost: bool,
+    rng: StdRng,
+    sender: Sender<(LocalTime, T)>,
+}
+
+impl<T> Link<T> {
+    fn new(clock: SyncClock, sender: Sender<(LocalTime, T)>) -> Self {
+        Self {
+            clock,
+            params: None,
+            last_lost: false,
+            rng: StdRng::from_entropy(),
+            sender,
+        }
+    }
+
+    fn send(&mut self, message: T) {
+        let time = self.clock.local_time();
+        let arrival_time = match self.params.as_ref() {
+            Some(params) => {
+                let residual = params.sample_residual(&mut self.rng, self.last_lost);
+                self.last_lost = residual.is_none();
+
+                match residual {
+                    Some(residual) => time + residual,
+                    None => return,
+                }
+            }
+            None => time,
+        };
+
+        // If the receiving end has been dropped, the message is lost.
+        let _ = self.sender.send((arrival_time, message));
+    }
+}
+
+/// Buffers received messages until their simulated arrival time.
+struct Inbox<T> {
+    clock: SyncClock,
+    receiver: Receiver<(LocalTime, T)>,
+    pending: TimeQueue<T>,
+}
+
+impl<T> Inbox<T> {
+    fn receive(&mut self) -> Vec<(LocalTime, T)> {
+        while let Ok((arrival_time, message)) = self.receiver.try_recv() {
+            self.pending.push(arrival_time, message);
+        }
+
+        let now = self.clock.local_time();
+        let mut messages = Vec::new();
+        while let Some((_, message)) = self.pending.pop_due(now) {
+            messages.push((now, message));
+        }
+
+        messages
+    }
+}
+
+/// The server end of an in-process transport built on [`mpsc`] channels.
+pub struct ChannelServerTransport<S, C> {
+    links: BTreeMap<PlayerId, Link<S>>,
+    inbox: Inbox<(PlayerId, C)>,
+}
+
+/// A client end of an in-process transport built on [`mpsc`] channels.
+pub struct ChannelClientTransport<S, C> {
+    player: PlayerId,
+    link: Link<(PlayerId, C)>,
+    inbox: Inbox<S>,
+}
+
+/// Create a transport between a server and the given players, whose ends
+/// can be moved to separate threads.
+///
+/// By default, messages arrive immediately and are never lost. Impairments
+/// can be added to each direction with `set_params`, and made reproducible
+/// with `set_seed`. Messages are
+/// timestamped with the time at which `receive` is called.
+pub fn channel_transport<S, C>(
+    players: &[PlayerId],
+    clock: SyncClock,
+) -> (
+    ChannelServerTransport<S, C>,
+    Vec<ChannelClientTransport<S, C>>,
+) {
+    let (client_sender, server_receiver) = mpsc::channel();
+    let mut links = BTreeMap::new();
+    let mut clients = Vec::new();
+
+    for player in players {
+        let (server_sender, client_receiver) = mpsc::channel();
+
+        let previous = links.insert(*player, Link::new(clock.clone(), server_sender));
+        assert!(previous.is_none(), "Duplicate PlayerId");
+
+        clients.push(ChannelClientTransport {
+            player: *player,
+            link: Link::new(clock.clone(), client_sender.clone()),
+            inbox: Inbox {
+                clock: clock.clone(),
+                receiver: client_receiver,
+                pending: TimeQueue::new(),
+            },
+        });
+    }
+
+    let server = ChannelServerTransport {
+        links,
+        inbox: Inbox {
+            clock,
+            receiver: server_receiver,
+            pending: TimeQueue::new(),
+        },
+    };
+
+    (server, clients)
+}
+
+impl<S, C> ChannelServerTransport<S, C> {
+    /// Impair messages that are sent to `player`.
+    pub fn set_params(&mut self, player: PlayerId, params: Option<MockChannelParams>) {
+        self.links
+            .get_mut(&player)
+            .expect("Unknown PlayerId")
+            .params = params;
+    }
+
+    /// Seed the random number generator that impairs messages sent to
+    /// `player`, so that runs can be reproduced.
+    pub fn set_seed(&mut self, player: PlayerId, seed: u64) {
+        self.links.get_mut(&player).expect("Unknown PlayerId").rng = StdRng::seed_from_u64(seed);
+    }
+}
+
+impl<S, C> ChannelClientTransport<S, C> {
+    pub fn player(&self) -> PlayerId {
+        self.player
+    }
+
+    /// Impair messages that are sent to the server.
+    pub fn set_params(&mut self, params: Option<MockChannelParams>) {
+        self.link.params = params;
+    }
+
+    /// Seed the random number generator that impairs messages sent to the
+    /// server, so that runs can be reproduced.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.link.rng = StdRng::seed_from_u64(seed);
+    }
+}
+
+impl<S, C> ServerTransport<S, C> for ChannelServerTransport<S, C> {
+    fn send(&mut self, receiver: PlayerId, message: S) {
+        self.links
+            .get_mut(&receiver)
+            .expect("Unknown PlayerId")
+            .send(message);
+    }
+
+    fn receive(&mut self) -> Vec<(LocalTime, PlayerId, C)> {
+        self.inbox
+            .receive()
+            .into_iter()
+            .map(|(receive_time, (sender, message))| (receive_time, sender, message))
+            .collect()
+    }
+}
+
+impl<S, C> ClientTransport<S, C> for ChannelClientTransport<S, C> {
+    fn send(&mut self, message: C) {
+        self.link.send((self.player, message));
+    }
+
+    fn receive(&mut self) -> Vec<(LocalTime, S)> {
+        self.inbox.receive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::channel_transport;
+    use crate::{
+        mock::MockChannelParams,
+        transport::{ClientTransport, ServerTransport},
+        LocalDt, PlayerId, SyncClock,
+    };
+
+    #[test]
+    fn test_threads() {
+        let clock = SyncClock::real_time();
+        let (mut server, clients) =
+            channel_transport::<(), f64>(&[PlayerId(0), PlayerId(1)], clock.clone());
+
+        let handles: Vec<_> = clients
+            .into_iter()
+            .map(|mut client| {
+                let clock = clock.clone();
+                client.set_params(Some(MockChannelParams {
+                    latency_mean: LocalDt::from_millis(20.0),
+                    ..MockChannelParams::perfect()
+                }));
+
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        client.send(clock.local_time().to_secs());
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                })
+            })
+            .collect();
+
+        let mut messages = Vec::new();
+        for _ in 0..200 {
+            messages.extend(server.receive());
+            if messages.len() == 20 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(messages.len(), 20);
+        for (receive_time, _, send_time) in messages {
+            assert!(receive_time.to_secs() - send_time > 0.019);
+        }
+    }
+
+    #[test]
+    fn test_seed() {
+        let run = || {
+            let (mut server, mut clients) =
+                channel_transport::<(), u32>(&[PlayerId(0)], SyncClock::real_time());
+            let client = &mut clients[0];
+            client.set_params(Some(MockChannelParams {
+                loss: 0.5,
+                ..MockChannelParams::perfect()
+            }));
+            client.set_seed(1);
+
+            for i in 0..100 {
+                client.send(i);
+            }
+            server
+                .receive()
+                .into_iter()
+                .map(|(_, _, message)| message)
+                .collect::<Vec<_>>()
+        };
+
+        let received = run();
+        assert!(!received.is_empty() && received.len() < 100);
+        assert_eq!(received, run());
+    }
+}
@@ -0,0 +1,35 @@
+//! Abstractions over the network, so that server and client logic can run on
+//! top of [`MockNet`](crate::mock::MockNet) as well as real sockets.
+//!
+//! `S` is the type of messages sent by the server, and `C` the type of
+//! messages sent by clients.
+
+mod channel;
+#[cfg(not(target_arch = "wasm32"))]
+mod udp;
+
+use crate::{LocalTime, PlayerId};
+
+pub use channel::{channel_transport, ChannelClientTransport, ChannelServerTransport};
+#[cfg(not(target_arch = "wasm32"))]
+pub use udp::{UdpClientTransport, UdpServerTransport};
+
+/// The server's end of a transport.
+pub trait ServerTransport<S, C> {
+    fn send(&mut self, receiver: PlayerId, message: S);
+
+    /// Receive all messages that have arrived from clients, sorted by their
+    /// receive time. Receive times are given in the server's
+    /// [`LocalClock`](crate::LocalClock).
+    fn receive(&mut self) -> Vec<(LocalTime, PlayerId, C)>;
+}
+
+/// A client's end of a transport.
+pub trait ClientTransport<S, C> {
+    fn send(&mut self, message: C);
+
+    /// Receive all messages that have arrived from the server, sorted by
+    /// their receive time. Receive times are given in the client's
+    /// [`LocalClock`](crate::LocalClock).
+    fn receive(&mut self) -> Vec<(LocalTime, S)>;
+}
@@ -0,0 +1,221 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+use crate::{LocalClock, LocalTime, PlayerId};
+
+use super::{ClientTransport, ServerTransport};
+
+/// Maximum size of a UDP payload.
+const MAX_DATAGRAM_SIZE: usize = 65507;
+
+/// Receive all datagrams that are currently queued on a non-blocking socket.
+fn receive_all(socket: &UdpSocket, buffer: &mut [u8]) -> Vec<(SocketAddr, Vec<u8>)> {
+    let mut datagrams = Vec::new();
+
+    loop {
+        match socket.recv_from(buffer) {
+            Ok((len, addr)) => datagrams.push((addr, buffer[..len].to_vec())),
+            Err(error) => match error.kind() {
+                io::ErrorKind::WouldBlock => return datagrams,
+                io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionRefused => {
+                    // On some platforms, ICMP errors from previous sends show
+                    // up here. They do not prevent further receives.
+                    log::debug!("Ignoring ICMP error while receiving: {}", error);
+                }
+                _ => {
+                    // Other errors may persist, so retrying could loop
+                    // forever. Try again in the next update instead.
+                    log::warn!("Failed to receive datagram: {}", error);
+                    return datagrams;
+                }
+            },
+        }
+    }
+}
+
+/// The server end of a UDP transport. Each datagram is one message.
+///
+/// Peers are identified by their address. A peer is assigned a new
+/// [`PlayerId`] when the first datagram from its address is received.
+pub struct UdpServerTransport {
+    socket: UdpSocket,
+    clock: LocalClock,
+    players: BTreeMap<PlayerId, SocketAddr>,
+    addrs: BTreeMap<SocketAddr, PlayerId>,
+    next_player: u32,
+    buffer: Vec<u8>,
+}
+
+impl UdpServerTransport {
+    /// Bind a non-blocking socket to `addr`. Received messages are
+    /// timestamped with `clock`.
+    pub fn bind(addr: impl ToSocketAddrs, clock: LocalClock) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            clock,
+            players: BTreeMap::new(),
+            addrs: BTreeMap::new(),
+            next_player: 0,
+            buffer: vec![0; MAX_DATAGRAM_SIZE],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = (PlayerId, SocketAddr)> + '_ {
+        self.players.iter().map(|(player, addr)| (*player, *addr))
+    }
+
+    pub fn player_addr(&self, player: PlayerId) -> Option<SocketAddr> {
+        self.players.get(&player).copied()
+    }
+
+    /// Forget a player. If its address sends again, it is treated as a new
+    /// player.
+    pub fn remove_player(&mut self, player: PlayerId) {
+        if let Some(addr) = self.players.remove(&player) {
+            self.addrs.remove(&addr);
+        }
+    }
+
+    fn player_for_addr(&mut self, addr: SocketAddr) -> PlayerId {
+        if let Some(player) = self.addrs.get(&addr) {
+            return *player;
+        }
+
+        let player = PlayerId(self.next_player);
+        self.next_player += 1;
+        self.players.insert(player, addr);
+        self.addrs.insert(addr, player);
+        player
+    }
+}
+
+impl ServerTransport<Vec<u8>, Vec<u8>> for UdpServerTransport {
+    fn send(&mut self, receiver: PlayerId, message: Vec<u8>) {
+        let addr = self.players.get(&receiver).expect("Unknown PlayerId");
+
+        if let Err(error) = self.socket.send_to(&message, addr) {
+            log::warn!("Failed to send datagram to {}: {}", addr, error);
+        }
+    }
+
+    /// Receive all queued datagrams. Since the socket is polled, all of them
+    /// are timestamped with the current time.
+    fn receive(&mut self) -> Vec<(LocalTime, PlayerId, Vec<u8>)> {
+        let time = self.clock.local_time();
+        receive_all(&self.socket, &mut self.buffer)
+            .into_iter()
+            .map(|(addr, message)| (time, self.player_for_addr(addr), message))
+            .collect()
+    }
+}
+
+/// The client end of a UDP transport. Each datagram is one message.
+pub struct UdpClientTransport {
+    socket: UdpSocket,
+    clock: LocalClock,
+    buffer: Vec<u8>,
+}
+
+impl UdpClientTransport {
+    /// Create a non-blocking socket that only exchanges datagrams with
+    /// `server_addr`. Received messages are timestamped with `clock`.
+    pub fn connect(server_addr: SocketAddr, clock: LocalClock) -> io::Result<Self> {
+        let bind_addr: SocketAddr = if server_addr.is_ipv4() {
+            "0.0.0.0:0".parse().unwrap()
+        } else {
+            "[::]:0".parse().unwrap()
+        };
+
+        let socket = UdpSocket::bind(bind_addr)?;
+        socket.connect(server_addr)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            clock,
+            buffer: vec![0; MAX_DATAGRAM_SIZE],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+impl ClientTransport<Vec<u8>, Vec<u8>> for UdpClientTransport {
+    fn send(&mut self, message: Vec<u8>) {
+        if let Err(error) = self.socket.send(&message) {
+            log::warn!("Failed to send datagram: {}", error);
+        }
+    }
+
+    /// Receive all queued datagrams. Since the socket is polled, all of them
+    /// are timestamped with the current time.
+    fn receive(&mut self) -> Vec<(LocalTime, Vec<u8>)> {
+        let time = self.clock.local_time();
+
+        receive_all(&self.socket, &mut self.buffer)
+            .into_iter()
+            .map(|(_, message)| (time, message))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::{UdpClientTransport, UdpServerTransport};
+    use crate::{
+        transport::{ClientTransport, ServerTransport},
+        LocalClock, LocalDt, PlayerId,
+    };
+
+    fn poll<T>(mut receive: impl FnMut() -> Vec<T>) -> Vec<T> {
+        for _ in 0..100 {
+            let messages = receive();
+            if !messages.is_empty() {
+                return messages;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        Vec::new()
+    }
+
+    #[test]
+    fn test_loopback() {
+        let mut clock = LocalClock::new();
+        let mut server = UdpServerTransport::bind("127.0.0.1:0", clock.clone()).unwrap();
+        let mut client =
+            UdpClientTransport::connect(server.local_addr().unwrap(), clock.clone()).unwrap();
+
+        client.send(b"input".to_vec());
+        clock.advance(LocalDt::from_secs(1.0));
+
+        let messages = poll(|| server.receive());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].0, clock.local_time());
+        assert_eq!(messages[0].1, PlayerId(0));
+        assert_eq!(messages[0].2, b"input".to_vec());
+        assert_eq!(
+            server.player_addr(PlayerId(0)),
+            Some(client.local_addr().unwrap())
+        );
+
+        server.send(PlayerId(0), b"snapshot".to_vec());
+        let messages = poll(|| client.receive());
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].1, b"snapshot".to_vec());
+    }
+}
@@ -0,0 +1,162 @@
+//! `#[derive(NetSerialize)]` for the `untimely` crate.
+//!
+//! Use it through the `derive` feature of `untimely` rather than depending on
+//! this crate directly.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Index};
+
+/// Derive `untimely::codec::NetSerialize` by encoding all fields in
+/// declaration order. Enums first encode the index of their variant, using
+/// only as many bits as the number of variants needs.
+#[proc_macro_derive(NetSerialize)]
+pub fn derive_net_serialize(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand(mut input: DeriveInput) -> syn::Result<TokenStream> {
+    for param in input.generics.type_params_mut() {
+        param
+            .bounds
+            .push(parse_quote!(::untimely::codec::NetSerialize));
+    }
+
+    let name = &input.ident;
+    let (serialize, deserialize) = match &input.data {
+        Data::Struct(data) => expand_struct(&data.fields),
+        Data::Enum(data) => {
+            if data.variants.is_empty() {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "NetSerialize can not be derived for enums without variants",
+                ));
+            }
+
+            let max_index = data.variants.len() as u64 - 1;
+            let mut serialize_arms = Vec::new();
+            let mut deserialize_arms = Vec::new();
+
+            for (index, variant) in data.variants.iter().enumerate() {
+                let index = index as u64;
+                let variant_name = &variant.ident;
+                let bindings = field_bindings(&variant.fields);
+                let pattern = construct(quote!(#name::#variant_name), &variant.fields, &bindings);
+                let deserialize_fields = deserialize_fields(&variant.fields);
+
+                serialize_arms.push(quote! {
+                    #pattern => {
+                        writer.write_bounded(#index, 0, #max_index);
+                        #(::untimely::codec::NetSerialize::serialize(#bindings, writer);)*
+                    }
+                });
+                deserialize_arms.push(quote! {
+                    #index => {
+                        #deserialize_fields
+                        ::std::result::Result::Ok(#pattern)
+                    }
+                });
+            }
+
+            (
+                quote! {
+                    match self {
+                        #(#serialize_arms)*
+                    }
+                },
+                quote! {
+                    match reader.read_bounded(0, #max_index)? {
+                        #(#deserialize_arms)*
+                        _ => ::std::result::Result::Err(::untimely::codec::CodecError::OutOfRange),
+                    }
+                },
+            )
+        }
+        Data::Union(data) => {
+            return Err(syn::Error::new_spanned(
+                data.union_token,
+                "NetSerialize can not be derived for unions",
+            ));
+        }
+    };
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::untimely::codec::NetSerialize for #name #ty_generics #where_clause {
+            // Unit structs and enums do not use the writer or the reader.
+            #[allow(unused_variables)]
+            fn serialize(&self, writer: &mut ::untimely::codec::BitWriter) {
+                #serialize
+            }
+
+            #[allow(unused_variables)]
+            fn deserialize(
+                reader: &mut ::untimely::codec::BitReader<'_>,
+            ) -> ::std::result::Result<Self, ::untimely::codec::CodecError> {
+                #deserialize
+            }
+        }
+    })
+}
+
+fn expand_struct(fields: &Fields) -> (TokenStream, TokenStream) {
+    let bindings = field_bindings(fields);
+    let pattern = construct(quote!(Self), fields, &bindings);
+    let deserialize_fields = deserialize_fields(fields);
+
+    (
+        quote! {
+            let #pattern = self;
+            #(::untimely::codec::NetSerialize::serialize(#bindings, writer);)*
+        },
+        quote! {
+            #deserialize_fields
+            ::std::result::Result::Ok(#pattern)
+        },
+    )
+}
+
+/// Local variable names for the fields, in declaration order.
+fn field_bindings(fields: &Fields) -> Vec<syn::Ident> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(index, field)| match &field.ident {
+            Some(ident) => format_ident!("field_{}", ident),
+            None => format_ident!("field_{}", index),
+        })
+        .collect()
+}
+
+/// Builds `path { a: field_a, .. }`, `path { 0: field_0, .. }` or `path`, which is
+/// used both as a pattern and as an expression.
+fn construct(path: TokenStream, fields: &Fields, bindings: &[syn::Ident]) -> TokenStream {
+    match fields {
+        Fields::Named(named) => {
+            let names = named.named.iter().map(|field| &field.ident);
+            quote!(#path { #(#names: #bindings),* })
+        }
+        Fields::Unnamed(unnamed) => {
+            let indices = (0..unnamed.unnamed.len()).map(Index::from);
+            quote!(#path { #(#indices: #bindings),* })
+        }
+        Fields::Unit => path,
+    }
+}
+
+fn deserialize_fields(fields: &Fields) -> TokenStream {
+    let bindings = field_bindings(fields);
+    let types = fields.iter().map(|field| &field.ty);
+
+    quote! {
+        #(
+            let #bindings = <#types as ::untimely::codec::NetSerialize>::deserialize(reader)?;
+        )*
+    }
+}